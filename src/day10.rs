@@ -1,92 +1,138 @@
 use aoc_runner_derive::aoc;
-use std::collections::HashSet;
 
-type Trails = (HashSet<(usize, usize)>, usize);
+// Trails always climb; a step may gain at most max_delta in height
+#[derive(Clone, Copy)]
+struct StepRule {
+    max_delta: u32,
+    diagonal: bool,
+}
+
+const GRADUAL: StepRule = StepRule {
+    max_delta: 1,
+    diagonal: false,
+};
+
+const DIRS: [(isize, isize); 8] = [
+    (1, 0),
+    (0, 1),
+    (-1, 0),
+    (0, -1),
+    (1, 1),
+    (-1, 1),
+    (-1, -1),
+    (1, -1),
+];
+
+// Summits reachable from a cell, as a bitset, and number of distinct trails
+type Trails = (Vec<u64>, usize);
 
 struct Map {
-    grid: Vec<Vec<u32>>,
-    height: usize,
-    width: usize,
+    grid: Vec<Vec<Option<u32>>>,
     starts: Vec<(usize, usize)>,
+    summits: Vec<Vec<Option<usize>>>,
+    summit_count: usize,
+    rule: StepRule,
     memo: Vec<Vec<Option<Trails>>>,
 }
 
 impl Map {
-    fn new(input: &str) -> Self {
+    fn new(input: &str, rule: StepRule) -> Self {
         let mut starts = Vec::new();
+        let mut summit_count = 0;
         let height = input.lines().count();
         let width = input.lines().next().unwrap().len();
-        let grid = input
+        let grid: Vec<Vec<_>> = input
             .lines()
-            .enumerate()
-            .map(|(i, l)| {
-                l.chars()
-                    .enumerate()
-                    .map(|(j, c)| {
-                        let val = c.to_digit(10).unwrap();
-                        if val == 0 {
-                            starts.push((i, j));
-                        }
-                        val
-                    })
-                    .collect()
-            })
+            .map(|l| l.chars().map(|c| c.to_digit(10)).collect())
             .collect();
+        let mut summits = vec![vec![None; width]; height];
+        for (i, row) in grid.iter().enumerate() {
+            for (j, &val) in row.iter().enumerate() {
+                match val {
+                    Some(0) => starts.push((i, j)),
+                    Some(9) => {
+                        summits[i][j] = Some(summit_count);
+                        summit_count += 1;
+                    }
+                    _ => {}
+                }
+            }
+        }
         Self {
             grid,
-            height,
-            width,
             starts,
+            summits,
+            summit_count,
+            rule,
             memo: vec![vec![None; width]; height],
         }
     }
 
     fn get_neighbors(&self, pos: (usize, usize)) -> Vec<(usize, usize)> {
         let (i, j) = pos;
-        let val = self.grid[i][j];
-        let mut res = Vec::new();
-        if i < self.height - 1 && self.grid[i + 1][j] == val + 1 {
-            res.push((i + 1, j));
-        }
-        if j < self.width - 1 && self.grid[i][j + 1] == val + 1 {
-            res.push((i, j + 1));
-        }
-        if i > 0 && self.grid[i - 1][j] == val + 1 {
-            res.push((i - 1, j));
-        }
-        if j > 0 && self.grid[i][j - 1] == val + 1 {
-            res.push((i, j - 1));
-        }
-        res
+        let Some(val) = self.grid[i][j] else {
+            return Vec::new();
+        };
+        let dirs = if self.rule.diagonal { 8 } else { 4 };
+        DIRS[..dirs]
+            .iter()
+            .filter_map(|&(di, dj)| {
+                let (ni, nj) = (i.checked_add_signed(di)?, j.checked_add_signed(dj)?);
+                let next = (*self.grid.get(ni)?.get(nj)?)?;
+                (next > val && next - val <= self.rule.max_delta).then_some((ni, nj))
+            })
+            .collect()
     }
 
-    fn compute_trails(&mut self, pos: (usize, usize)) -> Trails {
+    fn compute_trails(&mut self, pos: (usize, usize)) -> &Trails {
         let (i, j) = pos;
-        if let Some(res) = &self.memo[i][j] {
-            return res.clone();
+        if self.memo[i][j].is_none() {
+            let mut dests = vec![0; self.summit_count.div_ceil(64)];
+            let mut trails = 0;
+            if let Some(k) = self.summits[i][j] {
+                dests[k / 64] |= 1 << (k % 64);
+                trails = 1;
+            } else {
+                for neighbor in self.get_neighbors(pos) {
+                    let (n_dests, n_trails) = self.compute_trails(neighbor);
+                    dests.iter_mut().zip(n_dests).for_each(|(d, n)| *d |= n);
+                    trails += n_trails;
+                }
+            }
+            self.memo[i][j] = Some((dests, trails));
         }
-        let height = self.grid[i][j];
-        let mut dests = HashSet::new();
-        let mut trails = 0;
-        if height == 9 {
-            dests.insert((i, j));
-            trails = 1;
-        } else {
-            for &neighbor in self.get_neighbors(pos).iter() {
-                let (n_dests, n_trails) = self.compute_trails(neighbor);
-                dests = dests.union(&n_dests).copied().collect();
-                trails += n_trails;
+        self.memo[i][j].as_ref().unwrap()
+    }
+
+    fn trails_from(&self, start: (usize, usize)) -> Vec<Vec<(usize, usize)>> {
+        let mut paths = Vec::new();
+        let mut stack = vec![vec![start]];
+        while let Some(path) = stack.pop() {
+            let &(i, j) = path.last().unwrap();
+            if self.summits[i][j].is_some() {
+                paths.push(path);
+                continue;
+            }
+            for neighbor in self.get_neighbors((i, j)) {
+                let mut next = path.clone();
+                next.push(neighbor);
+                stack.push(next);
             }
         }
-        self.memo[i][j] = Some((dests.clone(), trails));
-        (dests, trails)
+        paths
     }
 
     fn count_dests(&mut self) -> usize {
         self.starts
             .clone()
             .into_iter()
-            .map(|pos| self.compute_trails(pos).0.len())
+            .map(|pos| {
+                self.compute_trails(pos)
+                    .0
+                    .iter()
+                    .map(|d| d.count_ones() as usize)
+                    .sum::<usize>()
+            })
             .sum()
     }
 
@@ -101,10 +147,128 @@ impl Map {
 
 #[aoc(day10, part1)]
 fn part1(input: &str) -> usize {
-    Map::new(input).count_dests()
+    Map::new(input, GRADUAL).count_dests()
 }
 
 #[aoc(day10, part2)]
 fn part2(input: &str) -> usize {
-    Map::new(input).count_trails()
+    Map::new(input, GRADUAL).count_trails()
+}
+
+#[aoc(day10, part2, Paths)]
+fn part2_paths(input: &str) -> usize {
+    let map = Map::new(input, GRADUAL);
+    map.starts
+        .iter()
+        .map(|&pos| map.trails_from(pos).len())
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "89010123
+78121874
+87430965
+96549874
+45678903
+32019012
+01329801
+10456732";
+
+    #[test]
+    fn example() {
+        assert_eq!(part1(EXAMPLE), 36);
+        assert_eq!(part2(EXAMPLE), 81);
+        assert_eq!(part2_paths(EXAMPLE), 81);
+    }
+
+    #[test]
+    fn impassable_cells() {
+        let forked = "..90..9\n...1.98\n...2..7\n6543456\n765.987\n876....\n987....";
+        assert_eq!(
+            part1("...0...\n...1...\n...2...\n6543456\n7.....7\n8.....8\n9.....9"),
+            2
+        );
+        assert_eq!(part1(forked), 4);
+        assert_eq!(part2(forked), 13);
+        assert_eq!(part2_paths(forked), 13);
+        let open = "012345\n123456\n234567\n345678\n4.6789\n56789.";
+        assert_eq!(part2(open), 227);
+        assert_eq!(part2_paths(open), 227);
+    }
+
+    #[test]
+    fn step_rules() {
+        let steep = StepRule {
+            max_delta: 3,
+            diagonal: false,
+        };
+        let diagonal = StepRule {
+            max_delta: 3,
+            diagonal: true,
+        };
+        let cases = [
+            ("0369", [0, 1, 1]),
+            ("0...\n.3..\n..6.\n...9", [0, 0, 1]),
+            ("036\n369", [0, 3, 3]),
+        ];
+        for (input, trails) in cases {
+            for (rule, expected) in [GRADUAL, steep, diagonal].into_iter().zip(trails) {
+                assert_eq!(Map::new(input, rule).count_trails(), expected, "\n{input}");
+            }
+        }
+    }
+
+    #[test]
+    fn trails_from() {
+        let map = Map::new(
+            ".....0.\n..4321.\n..5..2.\n..6543.\n..7..4.\n..8765.\n..9....",
+            GRADUAL,
+        );
+        let mut paths = map.trails_from(map.starts[0]);
+        paths.sort();
+        assert_eq!(
+            paths,
+            [
+                vec![
+                    (0, 5),
+                    (1, 5),
+                    (1, 4),
+                    (1, 3),
+                    (1, 2),
+                    (2, 2),
+                    (3, 2),
+                    (4, 2),
+                    (5, 2),
+                    (6, 2)
+                ],
+                vec![
+                    (0, 5),
+                    (1, 5),
+                    (2, 5),
+                    (3, 5),
+                    (3, 4),
+                    (3, 3),
+                    (3, 2),
+                    (4, 2),
+                    (5, 2),
+                    (6, 2)
+                ],
+                vec![
+                    (0, 5),
+                    (1, 5),
+                    (2, 5),
+                    (3, 5),
+                    (4, 5),
+                    (5, 5),
+                    (5, 4),
+                    (5, 3),
+                    (5, 2),
+                    (6, 2)
+                ],
+            ]
+        );
+    }
 }
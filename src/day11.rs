@@ -1,45 +1,113 @@
 use aoc_runner_derive::aoc;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::Hash;
+use std::ops::AddAssign;
 
-enum TransformedStone {
-    One(u64),
-    Two((u64, u64)),
+struct Rule<T> {
+    applies: fn(&T) -> bool,
+    transform: fn(&T) -> Vec<T>,
 }
 
-fn transform(stone: u64) -> TransformedStone {
-    match (stone, stone.to_string()) {
-        (0, _) => TransformedStone::One(1),
-        (_, s) if s.len() % 2 == 0 => {
-            let (s1, s2) = s.split_at(s.len() / 2);
-            TransformedStone::Two((s1.parse().unwrap(), s2.parse().unwrap()))
+fn digits(stone: u64) -> u32 {
+    stone.checked_ilog10().unwrap_or(0) + 1
+}
+
+// The first rule that applies to a stone is used
+const RULES: [Rule<u64>; 3] = [
+    Rule {
+        applies: |&stone| stone == 0,
+        transform: |_| vec![1],
+    },
+    Rule {
+        applies: |&stone| digits(stone).is_multiple_of(2),
+        transform: |&stone| {
+            let half = 10u64.pow(digits(stone) / 2);
+            vec![stone / half, stone % half]
+        },
+    },
+    Rule {
+        applies: |_| true,
+        transform: |&stone| vec![stone * 2024],
+    },
+];
+
+fn apply_rules<T: Clone>(rules: &[Rule<T>], stone: &T) -> Vec<T> {
+    rules
+        .iter()
+        .find(|rule| (rule.applies)(stone))
+        .map_or(vec![stone.clone()], |rule| (rule.transform)(stone))
+}
+
+fn blink<T, C>(rules: &[Rule<T>], population: &HashMap<T, C>) -> HashMap<T, C>
+where
+    T: Clone + Eq + Hash,
+    C: Default + for<'a> AddAssign<&'a C>,
+{
+    let mut next: HashMap<T, C> = HashMap::new();
+    for (stone, count) in population {
+        for s in apply_rules(rules, stone) {
+            *next.entry(s).or_default() += count;
         }
-        _ => TransformedStone::One(stone * 2024),
     }
+    next
+}
+
+struct Evolution {
+    histograms: Vec<HashMap<u64, usize>>,
+    converged_at: Option<usize>,
 }
 
-fn count_stones(stone: u64, steps: usize, memo: &mut HashMap<(u64, usize), usize>) -> usize {
-    if steps == 0 {
-        return 1;
+impl fmt::Display for Evolution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (step, histogram) in self.histograms.iter().enumerate() {
+            writeln!(
+                f,
+                "{step}: {} stones, {} distinct values",
+                histogram.values().sum::<usize>(),
+                histogram.len()
+            )?;
+        }
+        write!(f, "converged at: {:?}", self.converged_at)
     }
-    if let Some(res) = memo.get(&(stone, steps)) {
-        return *res;
+}
+
+// Histogram of stone values after each blink, and the first blink after which
+// no new value ever appears
+fn evolve(input: &str, rules: &[Rule<u64>], steps: usize) -> Evolution {
+    let mut population: HashMap<u64, usize> = HashMap::new();
+    for stone in input.split(' ') {
+        *population.entry(stone.parse().unwrap()).or_default() += 1;
     }
-    let res = match transform(stone) {
-        TransformedStone::One(s) => count_stones(s, steps - 1, memo),
-        TransformedStone::Two((s1, s2)) => {
-            count_stones(s1, steps - 1, memo) + count_stones(s2, steps - 1, memo)
+    let mut seen: HashSet<u64> = population.keys().copied().collect();
+    let mut converged_at = None;
+    let mut histograms = vec![population.clone()];
+    for step in 1..=steps {
+        let next = blink(rules, &population);
+        let new_values = next.keys().filter(|&&s| seen.insert(s)).count();
+        match (new_values, converged_at) {
+            (0, None) => converged_at = Some(step),
+            (0, _) => {}
+            _ => converged_at = None,
         }
-    };
-    memo.insert((stone, steps), res);
-    res
+        population = next;
+        histograms.push(population.clone());
+    }
+    Evolution {
+        histograms,
+        converged_at,
+    }
 }
 
 fn run(input: &str, steps: usize) -> usize {
-    let mut memo = HashMap::new();
-    input
-        .split(' ')
-        .map(|s| count_stones(s.parse().unwrap(), steps, &mut memo))
-        .sum()
+    let mut population: HashMap<u64, usize> = HashMap::new();
+    for stone in input.split(' ') {
+        *population.entry(stone.parse().unwrap()).or_default() += 1;
+    }
+    for _ in 0..steps {
+        population = blink(&RULES, &population);
+    }
+    population.values().sum()
 }
 
 #[aoc(day11, part1)]
@@ -51,3 +119,202 @@ fn part1(input: &str) -> usize {
 fn part2(input: &str) -> usize {
     run(input, 75)
 }
+
+#[aoc(day11, part2, Population)]
+fn part2_population(input: &str) -> usize {
+    let evolution = evolve(input, &RULES, 75);
+    evolution.histograms.last().unwrap().values().sum()
+}
+
+#[cfg(feature = "bigint")]
+#[aoc(day11, part2, BigInt)]
+fn part2_bigint(input: &str) -> bigint::BigUint {
+    bigint::run(input, 75)
+}
+
+// Arbitrary precision stones and counts, stored as base 10^9 limbs so that
+// digit counting and splitting stay cheap
+#[cfg(feature = "bigint")]
+mod bigint {
+    use super::{blink, Rule};
+    use std::collections::HashMap;
+    use std::fmt;
+    use std::ops::AddAssign;
+
+    const BASE: u64 = 1_000_000_000;
+
+    #[derive(Clone, PartialEq, Eq, Hash, Default)]
+    pub struct BigUint(Vec<u32>);
+
+    impl BigUint {
+        fn from_u64(mut value: u64) -> Self {
+            let mut limbs = Vec::new();
+            while value > 0 {
+                limbs.push((value % BASE) as u32);
+                value /= BASE;
+            }
+            BigUint(limbs)
+        }
+
+        fn parse(input: &str) -> Self {
+            let bytes = input.as_bytes();
+            let limbs = bytes
+                .rchunks(9)
+                .map(|c| std::str::from_utf8(c).unwrap().parse().unwrap())
+                .collect();
+            BigUint(limbs).normalized()
+        }
+
+        fn normalized(mut self) -> Self {
+            while self.0.last() == Some(&0) {
+                self.0.pop();
+            }
+            self
+        }
+
+        fn is_zero(&self) -> bool {
+            self.0.is_empty()
+        }
+
+        fn digits(&self) -> u32 {
+            match self.0.last() {
+                None => 1,
+                Some(top) => 9 * (self.0.len() as u32 - 1) + top.ilog10() + 1,
+            }
+        }
+
+        // (self / 10^k, self % 10^k)
+        fn split(&self, k: u32) -> (Self, Self) {
+            let (q, r) = ((k / 9) as usize, k % 9);
+            let pow = 10u32.pow(r);
+            let mut low = self.0[..q.min(self.0.len())].to_vec();
+            if let Some(limb) = self.0.get(q) {
+                low.push(limb % pow);
+            }
+            let high = (q..self.0.len())
+                .map(|i| {
+                    let next = self.0.get(i + 1).map_or(0, |l| l % pow);
+                    self.0[i] / pow + next * (BASE as u32 / pow)
+                })
+                .collect();
+            (BigUint(high).normalized(), BigUint(low).normalized())
+        }
+
+        fn mul_small(&self, factor: u64) -> Self {
+            let mut carry = 0;
+            let mut limbs: Vec<u32> = self
+                .0
+                .iter()
+                .map(|&l| {
+                    let v = l as u64 * factor + carry;
+                    carry = v / BASE;
+                    (v % BASE) as u32
+                })
+                .collect();
+            while carry > 0 {
+                limbs.push((carry % BASE) as u32);
+                carry /= BASE;
+            }
+            BigUint(limbs)
+        }
+    }
+
+    impl AddAssign<&BigUint> for BigUint {
+        fn add_assign(&mut self, other: &BigUint) {
+            if self.0.len() < other.0.len() {
+                self.0.resize(other.0.len(), 0);
+            }
+            let mut carry = 0;
+            for (i, limb) in self.0.iter_mut().enumerate() {
+                let v = *limb as u64 + other.0.get(i).copied().unwrap_or(0) as u64 + carry;
+                *limb = (v % BASE) as u32;
+                carry = v / BASE;
+            }
+            if carry > 0 {
+                self.0.push(carry as u32);
+            }
+        }
+    }
+
+    impl fmt::Display for BigUint {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            match self.0.split_last() {
+                None => write!(f, "0"),
+                Some((top, rest)) => {
+                    write!(f, "{top}")?;
+                    rest.iter().rev().try_for_each(|l| write!(f, "{l:09}"))
+                }
+            }
+        }
+    }
+
+    const RULES: [Rule<BigUint>; 3] = [
+        Rule {
+            applies: BigUint::is_zero,
+            transform: |_| vec![BigUint::from_u64(1)],
+        },
+        Rule {
+            applies: |stone| stone.digits().is_multiple_of(2),
+            transform: |stone| {
+                let (high, low) = stone.split(stone.digits() / 2);
+                vec![high, low]
+            },
+        },
+        Rule {
+            applies: |_| true,
+            transform: |stone| vec![stone.mul_small(2024)],
+        },
+    ];
+
+    pub fn run(input: &str, steps: usize) -> BigUint {
+        let mut population: HashMap<BigUint, BigUint> = HashMap::new();
+        for stone in input.split(' ') {
+            *population.entry(BigUint::parse(stone)).or_default() += &BigUint::from_u64(1);
+        }
+        for _ in 0..steps {
+            population = blink(&RULES, &population);
+        }
+        population
+            .values()
+            .fold(BigUint::default(), |mut acc, count| {
+                acc += count;
+                acc
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn example() {
+        assert_eq!(run("125 17", 6), 22);
+        assert_eq!(part1("125 17"), 55312);
+        assert_eq!(part2_population("125 17"), part2("125 17"));
+    }
+
+    // Stones around the limb boundary and with up to 18 digits, which still fit
+    // the u64 rules for 75 blinks
+    #[cfg(feature = "bigint")]
+    #[test]
+    fn bigint_matches_u64() {
+        let inputs = [
+            "125 17",
+            "999999999",
+            "1000000000",
+            "999999999 1000000000 1000000001",
+            "100000000000000000",
+            "123456789012345678 100000000000000001",
+        ];
+        for input in inputs {
+            for steps in [25, 75] {
+                assert_eq!(
+                    bigint::run(input, steps).to_string(),
+                    run(input, steps).to_string(),
+                    "{input}"
+                );
+            }
+        }
+    }
+}
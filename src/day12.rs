@@ -1,6 +1,41 @@
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::Itertools;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+const SIDES: [(isize, isize); 4] = [(-1, 0), (0, 1), (1, 0), (0, -1)];
+
+struct Region {
+    plant: char,
+    cells: Vec<(usize, usize)>,
+    perimeter: usize,
+    sides: usize,
+    bounds: ((usize, usize), (usize, usize)),
+    holes: usize,
+    outline: Vec<(usize, usize)>,
+}
+
+impl Region {
+    fn area(&self) -> usize {
+        self.cells.len()
+    }
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: area {}, perimeter {}, {} sides, bounds {:?}, {} holes, outline {:?}",
+            self.plant,
+            self.area(),
+            self.perimeter,
+            self.sides,
+            self.bounds,
+            self.holes,
+            self.outline
+        )
+    }
+}
 
 struct Grid {
     data: Vec<Vec<char>>,
@@ -44,11 +79,11 @@ impl Grid {
         let (i, j) = pos;
         (match dir {
             0 if i < self.height - 1 => Some((i + 1, j)),
-            1 if j < self.height - 1 => Some((i, j + 1)),
+            1 if j < self.width - 1 => Some((i, j + 1)),
             2 if i > 0 => Some((i - 1, j)),
             3 if j > 0 => Some((i, j - 1)),
             4 if i < self.height - 1 && j < self.width - 1 => Some((i + 1, j + 1)),
-            5 if i > 0 && j < self.height - 1 => Some((i - 1, j + 1)),
+            5 if i > 0 && j < self.width - 1 => Some((i - 1, j + 1)),
             6 if i > 0 && j > 0 => Some((i - 1, j - 1)),
             7 if i < self.height - 1 && j > 0 => Some((i + 1, j - 1)),
             _ => None,
@@ -71,21 +106,32 @@ impl Grid {
             .count()
     }
 
-    fn get_area_and_edges(
+    fn flood(
         &self,
         start: (usize, usize),
         visited: &mut HashSet<(usize, usize)>,
+        measure: impl Fn((usize, usize), &[(usize, usize)]) -> usize,
     ) -> (usize, usize) {
         if !visited.insert(start) {
             return (0, 0);
         }
-        let neighbors = self.get_valid_neighbors(start);
-        neighbors
-            .iter()
-            .fold((1, 4 - neighbors.len()), |(a, p), &n| {
-                let (na, np) = self.get_area_and_edges(n, visited);
-                (a + na, p + np)
-            })
+        let mut stack = vec![start];
+        let (mut area, mut total) = (0, 0);
+        while let Some(pos) = stack.pop() {
+            let neighbors = self.get_valid_neighbors(pos);
+            area += 1;
+            total += measure(pos, &neighbors);
+            stack.extend(neighbors.into_iter().filter(|&n| visited.insert(n)));
+        }
+        (area, total)
+    }
+
+    fn get_area_and_edges(
+        &self,
+        start: (usize, usize),
+        visited: &mut HashSet<(usize, usize)>,
+    ) -> (usize, usize) {
+        self.flood(start, visited, |_, neighbors| 4 - neighbors.len())
     }
 
     fn get_area_and_corners(
@@ -93,16 +139,170 @@ impl Grid {
         start: (usize, usize),
         visited: &mut HashSet<(usize, usize)>,
     ) -> (usize, usize) {
-        if !visited.insert(start) {
-            return (0, 0);
+        self.flood(start, visited, |pos, _| self.count_corners(pos))
+    }
+}
+
+impl Grid {
+    fn offset(&self, (i, j): (usize, usize), (di, dj): (isize, isize)) -> Option<(usize, usize)> {
+        let (ni, nj) = (i.checked_add_signed(di)?, j.checked_add_signed(dj)?);
+        (ni < self.height && nj < self.width).then_some((ni, nj))
+    }
+
+    fn regions(&self) -> Vec<Region> {
+        let mut labels = vec![vec![usize::MAX; self.width]; self.height];
+        let mut regions = Vec::new();
+        for start in (0..self.height).cartesian_product(0..self.width) {
+            if labels[start.0][start.1] != usize::MAX {
+                continue;
+            }
+            let label = regions.len();
+            let plant = self.data[start.0][start.1];
+            let mut cells = Vec::new();
+            let mut stack = vec![start];
+            labels[start.0][start.1] = label;
+            while let Some(pos) = stack.pop() {
+                cells.push(pos);
+                for n in SIDES.iter().filter_map(|&d| self.offset(pos, d)) {
+                    if labels[n.0][n.1] == usize::MAX && self.data[n.0][n.1] == plant {
+                        labels[n.0][n.1] = label;
+                        stack.push(n);
+                    }
+                }
+            }
+            regions.push(self.describe(plant, cells, |p| labels[p.0][p.1] == label));
         }
-        self.get_valid_neighbors(start)
+        regions
+    }
+
+    fn describe(
+        &self,
+        plant: char,
+        mut cells: Vec<(usize, usize)>,
+        inside: impl Fn((usize, usize)) -> bool,
+    ) -> Region {
+        cells.sort();
+        let is_in = |pos, d| self.offset(pos, d).is_some_and(&inside);
+        let perimeter = cells
             .iter()
-            .fold((1, self.count_corners(start)), |(a, c), &n| {
-                let (na, nc) = self.get_area_and_corners(n, visited);
-                (a + na, c + nc)
+            .flat_map(|&pos| SIDES.iter().filter(move |&&d| !is_in(pos, d)))
+            .count();
+        let sides = cells
+            .iter()
+            .flat_map(|&pos| {
+                (0..4).filter(move |&k| {
+                    let (a, b) = (SIDES[k], SIDES[(k + 1) % 4]);
+                    let (in_a, in_b) = (is_in(pos, a), is_in(pos, b));
+                    (!in_a && !in_b) || (in_a && in_b && !is_in(pos, (a.0 + b.0, a.1 + b.1)))
+                })
             })
+            .count();
+        let min = (
+            cells.iter().map(|p| p.0).min().unwrap(),
+            cells.iter().map(|p| p.1).min().unwrap(),
+        );
+        let max = (
+            cells.iter().map(|p| p.0).max().unwrap(),
+            cells.iter().map(|p| p.1).max().unwrap(),
+        );
+        Region {
+            plant,
+            holes: self.count_holes((min, max), &inside),
+            outline: outline(&cells, is_in),
+            cells,
+            perimeter,
+            sides,
+            bounds: (min, max),
+        }
     }
+
+    // Components of other cells inside the bounding box that never reach its border
+    fn count_holes(
+        &self,
+        ((i0, j0), (i1, j1)): ((usize, usize), (usize, usize)),
+        inside: impl Fn((usize, usize)) -> bool,
+    ) -> usize {
+        let mut seen = HashSet::new();
+        let mut holes = 0;
+        for start in (i0..=i1).cartesian_product(j0..=j1) {
+            if inside(start) || !seen.insert(start) {
+                continue;
+            }
+            let mut stack = vec![start];
+            let mut enclosed = true;
+            while let Some((i, j)) = stack.pop() {
+                enclosed &= i > i0 && i < i1 && j > j0 && j < j1;
+                for n in SIDES.iter().filter_map(|&d| self.offset((i, j), d)) {
+                    if (i0..=i1).contains(&n.0)
+                        && (j0..=j1).contains(&n.1)
+                        && !inside(n)
+                        && seen.insert(n)
+                    {
+                        stack.push(n);
+                    }
+                }
+            }
+            holes += enclosed as usize;
+        }
+        holes
+    }
+}
+
+// Corners of the outer boundary as lattice points, walking clockwise from the
+// top-left corner of the first cell. Boundary edges keep the region on their
+// right, and where the boundary touches itself the walk turns left first so
+// that it stays on the outside.
+fn outline(
+    cells: &[(usize, usize)],
+    is_in: impl Fn((usize, usize), (isize, isize)) -> bool,
+) -> Vec<(usize, usize)> {
+    let mut edges: HashSet<((usize, usize), usize)> = HashSet::new();
+    for &(i, j) in cells {
+        let corners = [(i, j), (i, j + 1), (i + 1, j + 1), (i + 1, j)];
+        for k in 0..4 {
+            if !is_in((i, j), SIDES[k]) {
+                edges.insert((corners[k], (k + 1) % 4));
+            }
+        }
+    }
+    let start = (cells[0], 1);
+    let (mut vertex, mut dir) = start;
+    let mut res = vec![vertex];
+    loop {
+        let (di, dj) = SIDES[dir];
+        vertex = (
+            vertex.0.wrapping_add_signed(di),
+            vertex.1.wrapping_add_signed(dj),
+        );
+        let next = [3, 0, 1]
+            .map(|turn| (dir + turn) % 4)
+            .into_iter()
+            .find(|&d| edges.contains(&(vertex, d)))
+            .unwrap();
+        if (vertex, next) == start {
+            return res;
+        }
+        if next != dir {
+            res.push(vertex);
+        }
+        dir = next;
+    }
+}
+
+fn perimeter_cost(region: &Region) -> usize {
+    region.perimeter
+}
+
+fn sides_cost(region: &Region) -> usize {
+    region.sides
+}
+
+// Plants without a custom rate are charged 1 per unit
+fn price(regions: &[Region], rates: &HashMap<char, usize>, cost: fn(&Region) -> usize) -> usize {
+    regions
+        .iter()
+        .map(|r| r.area() * cost(r) * rates.get(&r.plant).unwrap_or(&1))
+        .sum()
 }
 
 fn run<T>(input: &Grid, method: T) -> usize
@@ -133,3 +333,90 @@ fn part1(input: &Grid) -> usize {
 fn part2(input: &Grid) -> usize {
     run(input, Grid::get_area_and_corners)
 }
+
+#[aoc(day12, part1, Regions)]
+fn part1_regions(input: &Grid) -> usize {
+    price(&input.regions(), &HashMap::new(), perimeter_cost)
+}
+
+#[aoc(day12, part2, Regions)]
+fn part2_regions(input: &Grid) -> usize {
+    let regions = input.regions();
+    price(&regions, &HashMap::new(), sides_cost)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Lcg;
+
+    const WIDE: &str = "AAAAAAA\nABBBBBA\nAAAAAAA";
+    const TALL: &str = "AAA\nABA\nABA\nABA\nABA\nABA\nAAA";
+
+    #[test]
+    fn rectangular_sides() {
+        // A: area 16 with 4 outer and 4 inner sides, B: area 5 with 4 sides
+        for map in [WIDE, TALL] {
+            let grid = Grid::new(map);
+            assert_eq!(part2(&grid), 148);
+            assert_eq!(part2_regions(&grid), 148);
+        }
+    }
+
+    #[test]
+    fn holes_and_outlines() {
+        let cases = [
+            (
+                "AAAAAA\nAAABBA\nAAABBA\nABBAAA\nABBAAA\nAAAAAA",
+                vec![
+                    "A: area 28, perimeter 40, 12 sides, bounds ((0, 0), (5, 5)), 2 holes, \
+                     outline [(0, 0), (0, 6), (6, 6), (6, 0)]",
+                    "B: area 4, perimeter 8, 4 sides, bounds ((1, 3), (2, 4)), 0 holes, \
+                     outline [(1, 3), (1, 5), (3, 5), (3, 3)]",
+                    "B: area 4, perimeter 8, 4 sides, bounds ((3, 1), (4, 2)), 0 holes, \
+                     outline [(3, 1), (3, 3), (5, 3), (5, 1)]",
+                ],
+            ),
+            // The outline touches itself at (2, 2) and goes around the corner cell
+            (
+                "AAA\nA.A\nAA.",
+                vec![
+                    "A: area 7, perimeter 16, 10 sides, bounds ((0, 0), (2, 2)), 1 holes, \
+                     outline [(0, 0), (0, 3), (2, 3), (2, 2), (3, 2), (3, 0)]",
+                    ".: area 1, perimeter 4, 4 sides, bounds ((1, 1), (1, 1)), 0 holes, \
+                     outline [(1, 1), (1, 2), (2, 2), (2, 1)]",
+                    ".: area 1, perimeter 4, 4 sides, bounds ((2, 2), (2, 2)), 0 holes, \
+                     outline [(2, 2), (2, 3), (3, 3), (3, 2)]",
+                ],
+            ),
+        ];
+        for (map, expected) in &cases {
+            let regions = Grid::new(map).regions();
+            assert_eq!(
+                regions.iter().map(Region::to_string).collect::<Vec<_>>(),
+                *expected
+            );
+        }
+        assert_eq!(part2(&Grid::new(cases[0].0)), 368);
+    }
+
+    #[test]
+    fn random_rectangles() {
+        let mut rng = Lcg::new(12);
+        let mut next = |m: u64| rng.below(m);
+        for _ in 0..500 {
+            let (height, width) = (1 + next(9), 1 + next(9));
+            let map = (0..height)
+                .map(|_| {
+                    (0..width)
+                        .map(|_| (b'A' + next(3) as u8) as char)
+                        .collect::<String>()
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            let grid = Grid::new(&map);
+            assert_eq!(part1(&grid), part1_regions(&grid), "{map}");
+            assert_eq!(part2(&grid), part2_regions(&grid), "{map}");
+        }
+    }
+}
@@ -1,12 +1,13 @@
 use aoc_runner_derive::{aoc, aoc_generator};
 use nom::{
     bytes::complete::tag,
-    character::complete::{i64, multispace1},
+    character::complete::{alpha1, i64, multispace1, newline},
     combinator::map,
-    multi::separated_list0,
-    sequence::{preceded, tuple},
+    multi::{separated_list0, separated_list1},
+    sequence::{pair, preceded, separated_pair, terminated, tuple},
     IResult,
 };
+use std::{error::Error, fmt};
 
 // ax*A + bx*B = px
 // ay*A + by*B = py
@@ -60,6 +61,297 @@ impl System {
     }
 }
 
+fn floor_div(a: i128, b: i128) -> i128 {
+    let d = a / b;
+    if a % b != 0 && (a < 0) != (b < 0) {
+        d - 1
+    } else {
+        d
+    }
+}
+
+fn ceil_div(a: i128, b: i128) -> i128 {
+    -floor_div(-a, b)
+}
+
+// (g, x, y) with a*x + b*y = g
+fn ext_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        return (a.abs(), a.signum(), 0);
+    }
+    let (g, x, y) = ext_gcd(b, a % b);
+    (g, y, x - (a / b) * y)
+}
+
+// Values of k for which lo <= base + k * step <= hi, with step != 0
+fn k_range(base: i128, step: i128, lo: i128, hi: Option<i128>) -> (i128, i128) {
+    match (step > 0, hi) {
+        (true, Some(hi)) => (ceil_div(lo - base, step), floor_div(hi - base, step)),
+        (true, None) => (ceil_div(lo - base, step), i128::MAX),
+        (false, Some(hi)) => (ceil_div(hi - base, step), floor_div(lo - base, step)),
+        (false, None) => (i128::MIN, floor_div(lo - base, step)),
+    }
+}
+
+impl System {
+    // Cheapest non-negative (a, b) press counts, each at most `limit` if given
+    fn solve_presses(&self, costs: (i64, i64), limit: Option<i64>) -> Option<(i64, i64)> {
+        let [ax, ay, bx, by, px, py] =
+            [self.ax, self.ay, self.bx, self.by, self.px, self.py].map(|v| v as i128);
+        let (cost_a, cost_b) = (costs.0 as i128, costs.1 as i128);
+        let limit = limit.map(|l| l as i128);
+        let valid = |v: i128| v >= 0 && limit.is_none_or(|l| v <= l);
+        let det = ax * by - ay * bx;
+        if det != 0 {
+            let (an, bn) = (px * by - py * bx, ax * py - ay * px);
+            if an % det != 0 || bn % det != 0 {
+                return None;
+            }
+            let (a, b) = (an / det, bn / det);
+            return (valid(a) && valid(b)).then_some((a as i64, b as i64));
+        }
+        // Collinear buttons: the prize must be on the same line, and one
+        // equation with a non-zero coefficient then implies the other
+        if px * ay - py * ax != 0 || px * by - py * bx != 0 {
+            return None;
+        }
+        let (c1, c2, n) = if ax != 0 || bx != 0 {
+            (ax, bx, px)
+        } else if ay != 0 || by != 0 {
+            (ay, by, py)
+        } else {
+            return (px == 0 && py == 0).then_some((0, 0));
+        };
+        if c1 == 0 || c2 == 0 {
+            let (c, single) = if c1 == 0 { (c2, (0, 1)) } else { (c1, (1, 0)) };
+            if n % c != 0 || !valid(n / c) {
+                return None;
+            }
+            return Some(((n / c) as i64 * single.0, (n / c) as i64 * single.1));
+        }
+        let (g, x, y) = ext_gcd(c1, c2);
+        if n % g != 0 {
+            return None;
+        }
+        let (a0, b0) = (x * (n / g), y * (n / g));
+        let (step_a, step_b) = (c2 / g, -c1 / g);
+        let (lo_a, hi_a) = k_range(a0, step_a, 0, limit);
+        let (lo_b, hi_b) = k_range(b0, step_b, 0, limit);
+        let (lo, hi) = (lo_a.max(lo_b), hi_a.min(hi_b));
+        if lo > hi {
+            return None;
+        }
+        let slope = cost_a * step_a + cost_b * step_b;
+        // Costs are linear in k, so the cheapest solution is at a finite end of the range
+        let k = if slope > 0 || (slope == 0 && lo != i128::MIN) {
+            lo
+        } else {
+            hi
+        };
+        Some(((a0 + k * step_a) as i64, (b0 + k * step_b) as i64))
+    }
+}
+
+fn total_cost(systems: &[System], costs: (i64, i64), limit: Option<i64>) -> i64 {
+    systems
+        .iter()
+        .filter_map(|s| s.solve_presses(costs, limit))
+        .map(|(a, b)| costs.0 * a + costs.1 * b)
+        .sum()
+}
+
+// Any number of buttons moving along any number of signed axes
+struct Machine {
+    buttons: Vec<Vec<i64>>,
+    prize: Vec<i64>,
+}
+
+fn parse_axes(separator: &'static str) -> impl FnMut(&str) -> IResult<&str, Vec<i64>> {
+    move |input| separated_list1(tag(", "), preceded(pair(alpha1, tag(separator)), i64))(input)
+}
+
+fn parse_machine(input: &str) -> IResult<&str, Machine> {
+    map(
+        separated_pair(
+            separated_list1(
+                newline,
+                preceded(
+                    terminated(pair(tag("Button "), alpha1), tag(": ")),
+                    parse_axes(""),
+                ),
+            ),
+            newline,
+            preceded(tag("Prize: "), parse_axes("=")),
+        ),
+        |(buttons, prize)| Machine { buttons, prize },
+    )(input)
+}
+
+#[derive(Debug)]
+struct UnboundedPresses;
+
+impl fmt::Display for UnboundedPresses {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "no limit or same-signed axis bounds the presses")
+    }
+}
+
+impl Error for UnboundedPresses {}
+
+fn dot(a: &[i128], b: &[i128]) -> i128 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+impl Machine {
+    // Integer solutions of the machine's equations as x0 + kernel * z for any
+    // integer z, by unimodular column operations on the button vectors
+    fn lattice(&self) -> Option<(Vec<i128>, Vec<Vec<i128>>)> {
+        let (n, dims) = (self.buttons.len(), self.prize.len());
+        // Each column is a button followed by its combination of original buttons
+        let mut cols: Vec<Vec<i128>> = self
+            .buttons
+            .iter()
+            .enumerate()
+            .map(|(k, b)| {
+                let unit = (0..n).map(|i| (i == k) as i128);
+                b.iter().map(|&v| v as i128).chain(unit).collect()
+            })
+            .collect();
+        let mut y: Vec<i128> = Vec::new();
+        for (d, &p) in self.prize.iter().enumerate() {
+            let rank = y.len();
+            for j in rank + 1..n {
+                let (a, b) = (cols[rank][d], cols[j][d]);
+                if b == 0 {
+                    continue;
+                }
+                let (g, s, t) = ext_gcd(a, b);
+                let (u, v) = (cols[rank].clone(), cols[j].clone());
+                cols[rank] = u.iter().zip(&v).map(|(u, v)| s * u + t * v).collect();
+                cols[j] = u
+                    .iter()
+                    .zip(&v)
+                    .map(|(u, v)| a / g * v - b / g * u)
+                    .collect();
+            }
+            let residual = p as i128 - (0..rank).map(|k| cols[k][d] * y[k]).sum::<i128>();
+            match cols.get(rank).map(|c| c[d]) {
+                Some(pivot) if pivot != 0 => {
+                    if residual % pivot != 0 {
+                        return None;
+                    }
+                    y.push(residual / pivot);
+                }
+                _ if residual != 0 => return None,
+                _ => {}
+            }
+        }
+        let x0 = (0..n)
+            .map(|i| (0..y.len()).map(|k| y[k] * cols[k][dims + i]).sum())
+            .collect();
+        let kernel = cols[y.len()..].iter().map(|c| c[dims..].to_vec()).collect();
+        Some((x0, kernel))
+    }
+
+    // Press bound from the limit or an axis on which all buttons move the same way
+    fn press_bound(&self, button: usize, limit: Option<i128>) -> Option<i128> {
+        (0..self.prize.len())
+            .filter(|&d| self.buttons[button][d] != 0)
+            .filter(|&d| {
+                self.buttons.iter().all(|b| b[d] >= 0) || self.buttons.iter().all(|b| b[d] <= 0)
+            })
+            .map(|d| (self.prize[d] / self.buttons[button][d]) as i128)
+            .chain(limit)
+            .min()
+    }
+
+    // Cheapest presses costing less than `best`, if given. Costs are positive,
+    // so with a single free direction the cheapest presses are at an end of its
+    // range; with more, branch on the presses of a bounded button
+    fn cheapest(
+        &self,
+        cost: &[i128],
+        limit: Option<i128>,
+        best: Option<i128>,
+    ) -> Result<Option<(i128, Vec<i128>)>, UnboundedPresses> {
+        let Some((x0, kernel)) = self.lattice() else {
+            return Ok(None);
+        };
+        let cheaper = |x: Vec<i128>| {
+            let total = dot(cost, &x);
+            best.is_none_or(|b| total < b).then_some((total, x))
+        };
+        match &kernel[..] {
+            [] => {
+                let valid = x0.iter().all(|&v| v >= 0 && limit.is_none_or(|l| v <= l));
+                Ok(if valid { cheaper(x0) } else { None })
+            }
+            [v] => {
+                let (mut lo, mut hi) = (i128::MIN, i128::MAX);
+                for (&x, &step) in x0.iter().zip(v) {
+                    let (l, h) = match step {
+                        0 if x < 0 || limit.is_some_and(|l| x > l) => return Ok(None),
+                        0 => continue,
+                        _ => k_range(x, step, 0, limit),
+                    };
+                    (lo, hi) = (lo.max(l), hi.min(h));
+                }
+                if lo > hi {
+                    return Ok(None);
+                }
+                let z = if dot(cost, v) > 0 { lo } else { hi };
+                Ok(cheaper(x0.iter().zip(v).map(|(x, v)| x + z * v).collect()))
+            }
+            _ => {
+                let (bound, k) = (0..self.buttons.len())
+                    .filter(|&k| kernel.iter().any(|v| v[k] != 0))
+                    .filter_map(|k| Some((self.press_bound(k, limit)?, k)))
+                    .min()
+                    .ok_or(UnboundedPresses)?;
+                let mut rest = Machine {
+                    buttons: self.buttons.clone(),
+                    prize: self.prize.clone(),
+                };
+                let button = rest.buttons.remove(k);
+                let mut rest_cost = cost.to_vec();
+                rest_cost.remove(k);
+                let (mut best, mut res) = (best, None);
+                for p in 0..=bound {
+                    let fixed = p * cost[k];
+                    if best.is_some_and(|b| fixed >= b) {
+                        break;
+                    }
+                    let found = rest.cheapest(&rest_cost, limit, best.map(|b| b - fixed))?;
+                    if let Some((total, mut x)) = found {
+                        x.insert(k, p);
+                        best = Some(total + fixed);
+                        res = Some((total + fixed, x));
+                    }
+                    rest.prize
+                        .iter_mut()
+                        .zip(&button)
+                        .for_each(|(p, b)| *p -= b);
+                }
+                Ok(res)
+            }
+        }
+    }
+
+    // Cheapest non-negative press counts, each at most `limit` if given; buttons
+    // beyond the given costs cost 1 token
+    fn solve_presses(
+        &self,
+        costs: &[i64],
+        limit: Option<i64>,
+    ) -> Result<Option<Vec<i64>>, UnboundedPresses> {
+        let cost: Vec<_> = (0..self.buttons.len())
+            .map(|k| costs.get(k).copied().unwrap_or(1) as i128)
+            .collect();
+        let best = self.cheapest(&cost, limit.map(i128::from), None)?;
+        Ok(best.map(|(_, presses)| presses.into_iter().map(|v| v as i64).collect()))
+    }
+}
+
 #[aoc_generator(day13)]
 fn parse(input: &str) -> Vec<System> {
     separated_list0(multispace1, parse_system)(input).unwrap().1
@@ -74,3 +366,196 @@ fn part1(input: &[System]) -> i64 {
 fn part2(input: &[System]) -> i64 {
     input.iter().filter_map(|s| s.transform().solve()).sum()
 }
+
+#[aoc(day13, part1, Diophantine)]
+fn part1_diophantine(input: &[System]) -> i64 {
+    total_cost(input, (3, 1), Some(100))
+}
+
+#[aoc(day13, part2, Diophantine)]
+fn part2_diophantine(input: &[System]) -> i64 {
+    let systems: Vec<_> = input.iter().map(System::transform).collect();
+    total_cost(&systems, (3, 1), None)
+}
+
+#[aoc_generator(day13, part1, Machines)]
+fn parse_machines(input: &str) -> Vec<Machine> {
+    separated_list0(multispace1, parse_machine)(input)
+        .unwrap()
+        .1
+}
+
+#[aoc_generator(day13, part2, Machines)]
+fn parse_far_machines(input: &str) -> Vec<Machine> {
+    let mut machines = parse_machines(input);
+    for machine in machines.iter_mut() {
+        machine
+            .prize
+            .iter_mut()
+            .for_each(|p| *p += 10_000_000_000_000);
+    }
+    machines
+}
+
+fn machine_cost(machines: &[Machine], limit: Option<i64>) -> Result<i64, UnboundedPresses> {
+    let costs = [3, 1];
+    let mut total = 0;
+    for machine in machines {
+        if let Some(presses) = machine.solve_presses(&costs, limit)? {
+            total += presses
+                .iter()
+                .enumerate()
+                .map(|(k, p)| costs.get(k).unwrap_or(&1) * p)
+                .sum::<i64>();
+        }
+    }
+    Ok(total)
+}
+
+#[aoc(day13, part1, Machines)]
+fn part1_machines(input: &[Machine]) -> Result<i64, UnboundedPresses> {
+    machine_cost(input, Some(100))
+}
+
+#[aoc(day13, part2, Machines)]
+fn part2_machines(input: &[Machine]) -> Result<i64, UnboundedPresses> {
+    machine_cost(input, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Lcg;
+
+    const EXAMPLE: &str = "Button A: X+94, Y+34
+Button B: X+22, Y+67
+Prize: X=8400, Y=5400
+
+Button A: X+26, Y+66
+Button B: X+67, Y+21
+Prize: X=12748, Y=12176
+
+Button A: X+17, Y+86
+Button B: X+84, Y+37
+Prize: X=7870, Y=6450
+
+Button A: X+69, Y+23
+Button B: X+27, Y+71
+Prize: X=18641, Y=10279";
+
+    // Cheapest presses by enumerating every count up to `limit`
+    fn brute_force(machine: &Machine, costs: &[i64], limit: i64) -> Option<i64> {
+        let n = machine.buttons.len();
+        let mut presses = vec![0; n];
+        let mut best: Option<i64> = None;
+        loop {
+            let reached = (0..machine.prize.len()).all(|d| {
+                (0..n)
+                    .map(|k| presses[k] * machine.buttons[k][d])
+                    .sum::<i64>()
+                    == machine.prize[d]
+            });
+            if reached {
+                let cost = (0..n).map(|k| presses[k] * costs[k]).sum();
+                best = Some(best.map_or(cost, |b| b.min(cost)));
+            }
+            let Some(k) = (0..n).find(|&k| presses[k] < limit) else {
+                return best;
+            };
+            presses[..k].iter_mut().for_each(|p| *p = 0);
+            presses[k] += 1;
+        }
+    }
+
+    fn cost_of(machine: &Machine, presses: &[i64], costs: &[i64]) -> i64 {
+        for (d, &p) in machine.prize.iter().enumerate() {
+            let reached: i64 = (0..presses.len())
+                .map(|k| presses[k] * machine.buttons[k][d])
+                .sum();
+            assert_eq!(reached, p);
+        }
+        assert!(presses.iter().all(|&p| p >= 0));
+        presses.iter().zip(costs).map(|(p, c)| p * c).sum()
+    }
+
+    #[test]
+    fn example() {
+        assert_eq!(part1_machines(&parse_machines(EXAMPLE)).unwrap(), 480);
+        assert_eq!(
+            part2_machines(&parse_far_machines(EXAMPLE)).unwrap(),
+            part2(&parse(EXAMPLE))
+        );
+    }
+
+    #[test]
+    fn signed_buttons() {
+        let input = "Button A: X-2, Y+3
+Button B: X+5, Y-1
+Button C: X+1, Y+1
+Prize: X=7, Y=4
+
+Button A: X-2, Y+3
+Button B: X+5, Y-1
+Button C: X+1, Y+1
+Prize: X=8, Y=13";
+        let costs = [3, 1, 1];
+        for machine in parse_machines(input) {
+            let presses = machine.solve_presses(&costs, None).unwrap();
+            let cost = presses.map(|p| cost_of(&machine, &p, &costs));
+            assert_eq!(cost, brute_force(&machine, &costs, 40));
+        }
+    }
+
+    #[test]
+    fn far_collinear() {
+        let input = "Button A: X+1, Y+1\nButton B: X+3, Y+3\nPrize: X=17, Y=17";
+        assert_eq!(
+            part2_machines(&parse_far_machines(input)).unwrap(),
+            3333333333339
+        );
+        assert_eq!(part2_diophantine(&parse(input)), 3333333333339);
+    }
+
+    #[test]
+    fn unbounded() {
+        let input = "Button A: X+1\nButton B: X-1\nButton C: X+2\nPrize: X=1";
+        let machine = &parse_machines(input)[0];
+        assert!(machine.solve_presses(&[3, 1, 2], None).is_err());
+        let presses = machine.solve_presses(&[3, 1, 2], Some(5)).unwrap().unwrap();
+        assert_eq!(cost_of(machine, &presses, &[3, 1, 2]), 3);
+        assert!(part2_machines(&parse_machines(input)).is_err());
+    }
+
+    #[test]
+    fn random_machines() {
+        let mut rng = Lcg::new(13);
+        let mut next = |m: i64| rng.below(m as u64) as i64;
+        let costs = [3, 1, 2, 1];
+        for _ in 0..1000 {
+            let (n, dims) = (1 + next(4) as usize, 1 + next(3) as usize);
+            let buttons: Vec<Vec<i64>> = (0..n)
+                .map(|_| (0..dims).map(|_| next(9) - 4).collect())
+                .collect();
+            let presses: Vec<_> = (0..n).map(|_| next(7)).collect();
+            let prize = (0..dims)
+                .map(|d| (0..n).map(|k| presses[k] * buttons[k][d]).sum::<i64>() + next(2))
+                .collect();
+            let machine = Machine { buttons, prize };
+            let costs = &costs[..n];
+            let limited = machine.solve_presses(costs, Some(6)).unwrap();
+            assert_eq!(
+                limited.map(|p| cost_of(&machine, &p, costs)),
+                brute_force(&machine, costs, 6)
+            );
+            // Without a limit the cheapest presses may lie outside any box, and
+            // machines without a bound on their free buttons are rejected
+            let Ok(unlimited) = machine.solve_presses(costs, None) else {
+                continue;
+            };
+            let cost = unlimited.map(|p| cost_of(&machine, &p, costs));
+            if let Some(expected) = brute_force(&machine, costs, 10) {
+                assert!(cost.is_some_and(|c| c <= expected));
+            }
+        }
+    }
+}
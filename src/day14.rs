@@ -1,4 +1,4 @@
-use std::{cmp::Ordering, collections::HashSet};
+use std::{cmp::Ordering, collections::HashSet, error::Error, fmt};
 
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::Itertools;
@@ -18,15 +18,100 @@ struct Robot {
 
 impl Robot {
     fn step_n(&self, n: i32) -> (i32, i32) {
+        self.step_n_in(n, (101, 103))
+    }
+
+    fn step_n_in(&self, n: i32, (width, height): (i32, i32)) -> (i32, i32) {
         (
-            (self.pos.0 + n * self.vel.0).rem_euclid(101),
-            (self.pos.1 + n * self.vel.1).rem_euclid(103),
+            (self.pos.0 + n * self.vel.0).rem_euclid(width),
+            (self.pos.1 + n * self.vel.1).rem_euclid(height),
         )
     }
 }
 
-fn get_quadrant((x, y): (i32, i32)) -> Option<usize> {
-    match (x.cmp(&50), y.cmp(&51)) {
+fn variance(values: &[f64]) -> f64 {
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64
+}
+
+// Positions along one axis repeat every `size` steps; returns the offset where
+// robots are most clustered, with how many standard deviations its variance
+// lies below the average one, or 0 when every offset is alike
+fn clustering_offset(
+    robots: &[Robot],
+    size: i32,
+    axis: fn((i32, i32)) -> i32,
+    grid: (i32, i32),
+) -> (i32, f64) {
+    let variances: Vec<_> = (0..size)
+        .map(|n| {
+            let values: Vec<_> = robots
+                .iter()
+                .map(|r| axis(r.step_n_in(n, grid)) as f64)
+                .collect();
+            variance(&values)
+        })
+        .collect();
+    let (offset, &best) = variances
+        .iter()
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(b.1))
+        .unwrap();
+    let mean = variances.iter().sum::<f64>() / variances.len() as f64;
+    let spread = variance(&variances).sqrt();
+    let confidence = if spread > 0.0 {
+        (mean - best) / spread
+    } else {
+        0.0
+    };
+    (offset as i32, confidence)
+}
+
+fn ext_gcd(a: i64, b: i64) -> (i64, i64, i64) {
+    if b == 0 {
+        return (a, 1, 0);
+    }
+    let (g, x, y) = ext_gcd(b, a % b);
+    (g, y, x - (a / b) * y)
+}
+
+// Smallest step clustered on both axes, combined with the Chinese Remainder
+// Theorem, and the weakest of the two axis confidences
+fn find_tree(robots: &[Robot], (width, height): (i32, i32)) -> Option<(i64, f64)> {
+    if robots.is_empty() {
+        return None;
+    }
+    let (ox, cx) = clustering_offset(robots, width, |p| p.0, (width, height));
+    let (oy, cy) = clustering_offset(robots, height, |p| p.1, (width, height));
+    let (w, h) = (width as i64, height as i64);
+    let (g, inv_w, _) = ext_gcd(w, h);
+    if g != 1 {
+        return None;
+    }
+    let k = ((oy - ox) as i64 * inv_w).rem_euclid(h);
+    Some((ox as i64 + k * w, cx.min(cy)))
+}
+
+// Robots moving at random score 2 to 3 standard deviations, a tree about 8
+const MIN_CONFIDENCE: f64 = 4.0;
+
+#[derive(Debug)]
+struct NoTree(f64);
+
+impl fmt::Display for NoTree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "no step stands out (confidence {:.1})", self.0)
+    }
+}
+
+impl Error for NoTree {}
+
+fn get_quadrant(pos: (i32, i32)) -> Option<usize> {
+    get_quadrant_in(pos, (101, 103))
+}
+
+fn get_quadrant_in((x, y): (i32, i32), (width, height): (i32, i32)) -> Option<usize> {
+    match (x.cmp(&(width / 2)), y.cmp(&(height / 2))) {
         (Ordering::Less, Ordering::Less) => Some(0),
         (Ordering::Less, Ordering::Greater) => Some(1),
         (Ordering::Greater, Ordering::Less) => Some(2),
@@ -63,6 +148,123 @@ fn display_grid(positions: HashSet<(i32, i32)>) {
     }
 }
 
+// Frame export, opted into with the export feature
+#[cfg(feature = "export")]
+mod export {
+    use super::{get_quadrant_in, Robot};
+    use itertools::Itertools;
+    use std::{fs, io, ops::RangeInclusive, path::Path};
+
+    #[derive(Clone, Copy)]
+    pub enum Format {
+        Pbm,
+        Pgm,
+        Text,
+    }
+
+    // Number of robots on each tile, indexed by row then column
+    fn counts(robots: &[Robot], n: i32, grid: (i32, i32)) -> Vec<Vec<u32>> {
+        let mut tiles = vec![vec![0; grid.0 as usize]; grid.1 as usize];
+        for robot in robots {
+            let (x, y) = robot.step_n_in(n, grid);
+            tiles[y as usize][x as usize] += 1;
+        }
+        tiles
+    }
+
+    fn safety_factor(tiles: &[Vec<u32>]) -> usize {
+        let grid = (tiles[0].len() as i32, tiles.len() as i32);
+        let mut quadrants = [0; 4];
+        for (y, row) in tiles.iter().enumerate() {
+            for (x, &count) in row.iter().enumerate() {
+                if let Some(idx) = get_quadrant_in((x as i32, y as i32), grid) {
+                    quadrants[idx] += count as usize;
+                }
+            }
+        }
+        quadrants.iter().product()
+    }
+
+    // Total robot count on each tile over a range of steps
+    fn heatmap(robots: &[Robot], steps: RangeInclusive<i32>, grid: (i32, i32)) -> Vec<Vec<u32>> {
+        let mut total = vec![vec![0; grid.0 as usize]; grid.1 as usize];
+        for n in steps {
+            for (row, counts) in total.iter_mut().zip(counts(robots, n, grid)) {
+                row.iter_mut().zip(counts).for_each(|(t, c)| *t += c);
+            }
+        }
+        total
+    }
+
+    // Plain (ASCII) netpbm encodings: any robot is a black pixel in PBM, and the
+    // count is scaled to a grey level in PGM
+    fn to_pbm(tiles: &[Vec<u32>]) -> String {
+        let rows = tiles
+            .iter()
+            .map(|row| row.iter().map(|&c| if c > 0 { '1' } else { '0' }).join(" "))
+            .join("\n");
+        format!("P1\n{} {}\n{rows}\n", tiles[0].len(), tiles.len())
+    }
+
+    fn to_pgm(tiles: &[Vec<u32>]) -> String {
+        let max = tiles.iter().flatten().copied().max().unwrap_or(0).max(1);
+        let rows = tiles.iter().map(|row| row.iter().join(" ")).join("\n");
+        format!("P2\n{} {}\n{max}\n{rows}\n", tiles[0].len(), tiles.len())
+    }
+
+    // Puzzle notation: robot count per tile, '.' when empty
+    fn to_text(tiles: &[Vec<u32>]) -> String {
+        tiles
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|&c| match c {
+                        0 => '.',
+                        _ => char::from_digit(c.min(9), 10).unwrap(),
+                    })
+                    .collect::<String>()
+            })
+            .join("\n")
+    }
+
+    // Writes one file per step for PBM and PGM, or a single animation.txt with a
+    // header line before each frame, in every given format, plus a PGM heatmap of
+    // the whole range. Returns the safety factor of each frame
+    pub fn export(
+        robots: &[Robot],
+        steps: RangeInclusive<i32>,
+        grid: (i32, i32),
+        formats: &[Format],
+        dir: &Path,
+    ) -> io::Result<Vec<usize>> {
+        fs::create_dir_all(dir)?;
+        let mut factors = Vec::new();
+        let mut animation = String::new();
+        for n in steps.clone() {
+            let tiles = counts(robots, n, grid);
+            let factor = safety_factor(&tiles);
+            factors.push(factor);
+            for format in formats {
+                match format {
+                    Format::Pbm => fs::write(dir.join(format!("step_{n:05}.pbm")), to_pbm(&tiles))?,
+                    Format::Pgm => fs::write(dir.join(format!("step_{n:05}.pgm")), to_pgm(&tiles))?,
+                    Format::Text => {
+                        animation += &format!("step {n} safety {factor}\n{}\n\n", to_text(&tiles))
+                    }
+                }
+            }
+        }
+        if formats.iter().any(|f| matches!(f, Format::Text)) {
+            fs::write(dir.join("animation.txt"), animation)?;
+        }
+        fs::write(
+            dir.join("heatmap.pgm"),
+            to_pgm(&heatmap(robots, steps, grid)),
+        )?;
+        Ok(factors)
+    }
+}
+
 #[aoc_generator(day14)]
 fn parse(input: &str) -> Vec<Robot> {
     separated_list0(newline, parse_robot)(input).unwrap().1
@@ -89,3 +291,154 @@ fn part2(input: &[Robot]) -> i32 {
     display_grid(input.iter().map(|robot| robot.step_n(n)).collect());
     n
 }
+
+#[aoc(day14, part2, Detect)]
+fn part2_detect(input: &[Robot]) -> Result<i64, NoTree> {
+    match find_tree(input, (101, 103)) {
+        Some((n, confidence)) if confidence >= MIN_CONFIDENCE => Ok(n),
+        found => Err(NoTree(found.map_or(0.0, |(_, confidence)| confidence))),
+    }
+}
+
+// Frames are written under day14/ in the working directory, one subdirectory
+// per runner
+#[cfg(feature = "export")]
+#[aoc(day14, part1, Export)]
+fn part1_export(input: &[Robot]) -> std::io::Result<usize> {
+    let dir = std::path::Path::new("day14/part1");
+    let factors = export::export(input, 0..=100, (101, 103), &[export::Format::Text], dir)?;
+    Ok(factors[100])
+}
+
+#[cfg(feature = "export")]
+#[aoc(day14, part2, Export)]
+fn part2_export(input: &[Robot]) -> std::io::Result<i64> {
+    let n = part2_detect(input).map_err(std::io::Error::other)?;
+    let steps = (n as i32 - 5).max(0)..=n as i32 + 5;
+    let formats = [export::Format::Pbm, export::Format::Pgm];
+    export::export(input, steps, (101, 103), &formats, "day14/part2".as_ref())?;
+    Ok(n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Lcg;
+
+    // Robots with random velocities, the first `clustered` of which gather in a
+    // 31x33 box at step `tree`
+    fn robots(next: &mut impl FnMut(u64) -> i32, tree: i32, clustered: usize) -> Vec<Robot> {
+        (0..500)
+            .map(|k| {
+                let vel = (next(201) - 100, next(207) - 103);
+                let at = if k < clustered {
+                    (40 + next(31), 30 + next(33))
+                } else {
+                    (next(101), next(103))
+                };
+                let pos = (
+                    (at.0 - tree * vel.0).rem_euclid(101),
+                    (at.1 - tree * vel.1).rem_euclid(103),
+                );
+                Robot { pos, vel }
+            })
+            .collect()
+    }
+
+    const EXAMPLE: &str = "p=0,4 v=3,-3
+p=6,3 v=-1,-3
+p=10,3 v=-1,2
+p=2,0 v=2,-1
+p=0,0 v=1,3
+p=3,0 v=-2,-2
+p=7,6 v=-1,-3
+p=3,0 v=-1,-2
+p=9,3 v=2,3
+p=7,3 v=-1,2
+p=2,4 v=2,-3
+p=9,5 v=-3,-3";
+
+    #[test]
+    fn example() {
+        let mut quadrants = [0; 4];
+        for robot in parse(EXAMPLE) {
+            if let Some(idx) = get_quadrant_in(robot.step_n_in(100, (11, 7)), (11, 7)) {
+                quadrants[idx] += 1;
+            }
+        }
+        assert_eq!(quadrants.iter().product::<usize>(), 12);
+    }
+
+    #[cfg(feature = "export")]
+    #[test]
+    fn export_frames() {
+        use export::Format;
+        use std::fs;
+
+        let dir = std::env::temp_dir().join(format!("day14_export_{}", std::process::id()));
+        let formats = [Format::Pbm, Format::Pgm, Format::Text];
+        let factors = export::export(&parse(EXAMPLE), 99..=100, (11, 7), &formats, &dir).unwrap();
+        assert_eq!(factors[1], 12);
+        let files: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .sorted()
+            .collect();
+        assert_eq!(
+            files,
+            [
+                "animation.txt",
+                "heatmap.pgm",
+                "step_00099.pbm",
+                "step_00099.pgm",
+                "step_00100.pbm",
+                "step_00100.pgm"
+            ]
+        );
+        let animation = fs::read_to_string(dir.join("animation.txt")).unwrap();
+        let last = animation.split("\n\n").nth(1).unwrap();
+        assert_eq!(
+            last,
+            "step 100 safety 12
+......2..1.
+...........
+1..........
+.11........
+.....1.....
+...12......
+.1....1...."
+        );
+        let pbm = fs::read_to_string(dir.join("step_00100.pbm")).unwrap();
+        assert!(pbm.starts_with("P1\n11 7\n0 0 0 0 0 0 1 0 0 1 0\n"));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn find_tree_steps() {
+        let mut rng = Lcg::new(14);
+        let mut next = |m: u64| rng.below(m) as i32;
+        for tree in [0, 1, 6587, 10402] {
+            let robots = robots(&mut next, tree, 300);
+            let (n, confidence) = find_tree(&robots, (101, 103)).unwrap();
+            assert_eq!(n, tree as i64);
+            assert!(confidence > MIN_CONFIDENCE, "{confidence}");
+            assert_eq!(part2_detect(&robots).unwrap(), tree as i64);
+        }
+        assert!(part2_detect(&robots(&mut next, 0, 0)).is_err());
+    }
+
+    #[test]
+    fn find_tree_degenerate() {
+        let still: Vec<_> = (0..10)
+            .map(|k| Robot {
+                pos: (k, 2 * k),
+                vel: (0, 0),
+            })
+            .collect();
+        assert_eq!(find_tree(&still, (101, 103)), Some((0, 0.0)));
+        assert!(part2_detect(&still).is_err());
+        assert_eq!(find_tree(&still, (100, 102)), None);
+        assert_eq!(find_tree(&[], (101, 103)), None);
+        assert!(part2_detect(&[]).is_err());
+    }
+}
@@ -1,8 +1,14 @@
 use aoc_runner_derive::aoc;
+use itertools::Itertools;
+use std::{cmp::Reverse, collections::HashSet, error::Error, fmt};
+
+type Change = ((usize, usize), char, char);
 
 struct Grid {
     data: Vec<Vec<char>>,
     robot: (usize, usize),
+    // Cells written by the last move, with their previous and new contents
+    journal: Vec<Change>,
 }
 
 fn update_pos((x, y): (usize, usize), dir: char) -> (usize, usize) {
@@ -34,7 +40,11 @@ impl Grid {
                     .collect()
             })
             .collect();
-        Self { data, robot }
+        Self {
+            data,
+            robot,
+            journal: Vec::new(),
+        }
     }
 
     fn new_wide(input: &str) -> Self {
@@ -56,7 +66,16 @@ impl Grid {
                     .collect()
             })
             .collect();
-        Self { data, robot }
+        Self {
+            data,
+            robot,
+            journal: Vec::new(),
+        }
+    }
+
+    fn set(&mut self, (x, y): (usize, usize), c: char) {
+        self.journal.push(((x, y), self.data[x][y], c));
+        self.data[x][y] = c;
     }
 
     fn push_standard_crate(&mut self, (x, y): (usize, usize), dir: char) {
@@ -66,8 +85,8 @@ impl Grid {
             _ => {}
         }
         if self.data[nx][ny] == '.' {
-            self.data[nx][ny] = self.data[x][y];
-            self.data[x][y] = '.';
+            self.set((nx, ny), self.data[x][y]);
+            self.set((x, y), '.');
         }
     }
 
@@ -91,13 +110,14 @@ impl Grid {
         if self.data[nx][ny + 1] == '[' {
             self.force_push_wide_crate_up_down((nx, ny + 1), dir);
         }
-        self.data[x][y] = '.';
-        self.data[x][y + 1] = '.';
-        self.data[nx][ny] = '[';
-        self.data[nx][ny + 1] = ']';
+        self.set((x, y), '.');
+        self.set((x, y + 1), '.');
+        self.set((nx, ny), '[');
+        self.set((nx, ny + 1), ']');
     }
 
     fn move_robot(&mut self, dir: char) {
+        self.journal.clear();
         let (nx, ny) = update_pos(self.robot, dir);
         match self.data[nx][ny] {
             '[' if (dir == 'v' || dir == '^') => {
@@ -131,6 +151,12 @@ impl Grid {
             .sum()
     }
 
+    fn render(&self) -> String {
+        let mut rows = self.data.clone();
+        rows[self.robot.0][self.robot.1] = '@';
+        rows.into_iter().map(String::from_iter).join("\n")
+    }
+
     fn run(&mut self, instructions: &str) {
         instructions
             .lines()
@@ -139,6 +165,328 @@ impl Grid {
     }
 }
 
+impl fmt::Display for Grid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.render())
+    }
+}
+
+struct Move {
+    dir: char,
+    from: (usize, usize),
+    to: (usize, usize),
+    changes: Vec<Change>,
+}
+
+impl Move {
+    fn blocked(&self) -> bool {
+        self.from == self.to
+    }
+
+    // New position of each box moved, by its left part for wide boxes
+    fn pushed_boxes(&self) -> Vec<(usize, usize)> {
+        self.changes
+            .iter()
+            .filter(|&&(_, _, c)| c == 'O' || c == '[')
+            .map(|&(pos, _, _)| pos)
+            .collect()
+    }
+}
+
+impl fmt::Display for Move {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.blocked() {
+            write!(f, "{}: blocked at {:?}", self.dir, self.from)
+        } else {
+            write!(
+                f,
+                "{}: {:?} -> {:?}, pushed {} boxes",
+                self.dir,
+                self.from,
+                self.to,
+                self.pushed_boxes().len()
+            )
+        }
+    }
+}
+
+// Steps through the instructions keeping a log of applied moves, so that any
+// move can be undone; the log length is the current instruction index
+struct Simulator {
+    grid: Grid,
+    instructions: Vec<char>,
+    log: Vec<Move>,
+}
+
+impl Simulator {
+    fn new(grid: Grid, instructions: &str) -> Self {
+        Self {
+            grid,
+            instructions: instructions.lines().flat_map(str::chars).collect(),
+            log: Vec::new(),
+        }
+    }
+
+    fn step_forward(&mut self) -> Option<&Move> {
+        let &dir = self.instructions.get(self.log.len())?;
+        let from = self.grid.robot;
+        self.grid.move_robot(dir);
+        self.log.push(Move {
+            dir,
+            from,
+            to: self.grid.robot,
+            changes: std::mem::take(&mut self.grid.journal),
+        });
+        self.log.last()
+    }
+
+    fn step_backward(&mut self) -> Option<Move> {
+        let last = self.log.pop()?;
+        for &((x, y), before, _) in last.changes.iter().rev() {
+            self.grid.data[x][y] = before;
+        }
+        self.grid.robot = last.from;
+        Some(last)
+    }
+
+    fn replay_to(&mut self, index: usize) {
+        let index = index.min(self.instructions.len());
+        while self.log.len() > index {
+            self.step_backward();
+        }
+        while self.log.len() < index {
+            self.step_forward();
+        }
+    }
+
+    // Warehouse after the first `index` instructions, displayed in the puzzle's format
+    fn state_at(&mut self, index: usize) -> &Grid {
+        self.replay_to(index);
+        &self.grid
+    }
+}
+
+fn simulate(grid: Grid, instructions: &str) -> usize {
+    let mut sim = Simulator::new(grid, instructions);
+    let end = sim.instructions.len();
+    sim.state_at(end).sum_gps()
+}
+
+#[derive(Debug)]
+enum WarehouseError {
+    UnknownTile(char, (usize, usize)),
+    UnknownInstruction(char, usize),
+}
+
+impl fmt::Display for WarehouseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WarehouseError::UnknownTile(c, pos) => write!(f, "unknown tile {c:?} at {pos:?}"),
+            WarehouseError::UnknownInstruction(c, idx) => {
+                write!(f, "unknown instruction {c:?} at index {idx}")
+            }
+        }
+    }
+}
+
+impl Error for WarehouseError {}
+
+type Dir = (isize, isize);
+
+fn parse_instructions(input: &str) -> Result<Vec<Dir>, WarehouseError> {
+    input
+        .chars()
+        .filter(|&c| c != '\n')
+        .enumerate()
+        .map(|(idx, c)| match c {
+            'v' => Ok((1, 0)),
+            '>' => Ok((0, 1)),
+            '^' => Ok((-1, 0)),
+            '<' => Ok((0, -1)),
+            c => Err(WarehouseError::UnknownInstruction(c, idx)),
+        })
+        .collect()
+}
+
+fn offset((x, y): (usize, usize), (dx, dy): Dir) -> Option<(usize, usize)> {
+    Some((x.checked_add_signed(dx)?, y.checked_add_signed(dy)?))
+}
+
+// Doubles the map horizontally as in part 2
+fn widen(input: &str) -> String {
+    input
+        .lines()
+        .map(|l| {
+            l.chars()
+                .map(|c| match c {
+                    '@' => "@.".to_string(),
+                    'O' => "[]".to_string(),
+                    c => format!("{c}{c}"),
+                })
+                .collect::<String>()
+        })
+        .join("\n")
+}
+
+#[derive(Clone, Copy)]
+enum Tile {
+    Empty,
+    Wall,
+    Box(usize),
+    Robot,
+}
+
+// Boxes are arbitrary polyominoes: each 'O' is a single cell, "[]" a wide box,
+// and 4-connected cells sharing any other letter form one box
+struct Warehouse {
+    tiles: Vec<Vec<Tile>>,
+    boxes: Vec<Vec<(usize, usize)>>,
+    robots: Vec<(usize, usize)>,
+}
+
+impl Warehouse {
+    fn new(input: &str) -> Result<Self, WarehouseError> {
+        let chars: Vec<Vec<char>> = input.lines().map(|l| l.chars().collect()).collect();
+        let mut warehouse = Self {
+            tiles: chars.iter().map(|r| vec![Tile::Empty; r.len()]).collect(),
+            boxes: Vec::new(),
+            robots: Vec::new(),
+        };
+        for (i, row) in chars.iter().enumerate() {
+            for (j, &c) in row.iter().enumerate() {
+                let cells = match c {
+                    '.' => continue,
+                    '#' => {
+                        warehouse.tiles[i][j] = Tile::Wall;
+                        continue;
+                    }
+                    '@' => {
+                        warehouse.tiles[i][j] = Tile::Robot;
+                        warehouse.robots.push((i, j));
+                        continue;
+                    }
+                    _ if !matches!(warehouse.tiles[i][j], Tile::Empty) => continue,
+                    'O' => vec![(i, j)],
+                    '[' if row.get(j + 1) == Some(&']') => vec![(i, j), (i, j + 1)],
+                    c if c.is_ascii_alphabetic() => {
+                        let mut cells = vec![(i, j)];
+                        let mut seen = HashSet::from([(i, j)]);
+                        let mut stack = vec![(i, j)];
+                        while let Some(pos) = stack.pop() {
+                            for dir in [(1, 0), (0, 1), (-1, 0), (0, -1)] {
+                                let Some((x, y)) = offset(pos, dir) else {
+                                    continue;
+                                };
+                                if chars.get(x).and_then(|r| r.get(y)) == Some(&c)
+                                    && seen.insert((x, y))
+                                {
+                                    cells.push((x, y));
+                                    stack.push((x, y));
+                                }
+                            }
+                        }
+                        cells
+                    }
+                    c => return Err(WarehouseError::UnknownTile(c, (i, j))),
+                };
+                for &(x, y) in &cells {
+                    warehouse.tiles[x][y] = Tile::Box(warehouse.boxes.len());
+                }
+                warehouse.boxes.push(cells);
+            }
+        }
+        Ok(warehouse)
+    }
+
+    fn tile(&self, (x, y): (usize, usize)) -> Tile {
+        self.tiles
+            .get(x)
+            .and_then(|r| r.get(y))
+            .copied()
+            .unwrap_or(Tile::Wall)
+    }
+
+    // Every box that has to move for `target` to be freed, or None when the push
+    // runs into a wall or a robot; nothing is modified until the whole push is known
+    fn plan_push(&self, target: (usize, usize), dir: Dir) -> Option<Vec<usize>> {
+        let mut moved = Vec::new();
+        let mut seen = HashSet::new();
+        let mut stack = vec![target];
+        while let Some(pos) = stack.pop() {
+            match self.tile(pos) {
+                Tile::Empty => {}
+                Tile::Wall | Tile::Robot => return None,
+                Tile::Box(id) => {
+                    if seen.insert(id) {
+                        moved.push(id);
+                        for &cell in &self.boxes[id] {
+                            stack.push(offset(cell, dir)?);
+                        }
+                    }
+                }
+            }
+        }
+        Some(moved)
+    }
+
+    fn move_robot(&mut self, robot: usize, dir: Dir) -> bool {
+        let from = self.robots[robot];
+        let Some(target) = offset(from, dir) else {
+            return false;
+        };
+        let Some(moved) = self.plan_push(target, dir) else {
+            return false;
+        };
+        for &id in &moved {
+            for &(x, y) in &self.boxes[id] {
+                self.tiles[x][y] = Tile::Empty;
+            }
+        }
+        for &id in &moved {
+            for cell in self.boxes[id].iter_mut() {
+                *cell = offset(*cell, dir).unwrap();
+                self.tiles[cell.0][cell.1] = Tile::Box(id);
+            }
+        }
+        self.tiles[from.0][from.1] = Tile::Empty;
+        self.tiles[target.0][target.1] = Tile::Robot;
+        self.robots[robot] = target;
+        true
+    }
+
+    // Every robot follows each instruction, the one furthest along the
+    // direction first so that robots in a row move together
+    fn run(&mut self, instructions: &[Dir]) {
+        let mut order: Vec<_> = (0..self.robots.len()).collect();
+        for &(dx, dy) in instructions {
+            order.sort_by_key(|&r| {
+                let (x, y) = self.robots[r];
+                Reverse(x as isize * dx + y as isize * dy)
+            });
+            for &r in &order {
+                self.move_robot(r, (dx, dy));
+            }
+        }
+    }
+
+    // Distance of the top-left corner of each box's bounding box
+    fn sum_gps(&self) -> usize {
+        self.boxes
+            .iter()
+            .map(|cells| {
+                100 * cells.iter().map(|c| c.0).min().unwrap()
+                    + cells.iter().map(|c| c.1).min().unwrap()
+            })
+            .sum()
+    }
+}
+
+fn run_shapes(grid_data: &str, instructions: &str) -> Result<usize, WarehouseError> {
+    let mut warehouse = Warehouse::new(grid_data)?;
+    warehouse.run(&parse_instructions(instructions)?);
+    Ok(warehouse.sum_gps())
+}
+
 #[aoc(day15, part1)]
 fn part1(input: &str) -> usize {
     let (grid_data, instructions) = input.split_once("\n\n").unwrap();
@@ -154,3 +502,179 @@ fn part2(input: &str) -> usize {
     grid.run(instructions);
     grid.sum_gps()
 }
+
+#[aoc(day15, part1, Simulator)]
+fn part1_simulator(input: &str) -> usize {
+    let (grid_data, instructions) = input.split_once("\n\n").unwrap();
+    simulate(Grid::new(grid_data), instructions)
+}
+
+#[aoc(day15, part2, Simulator)]
+fn part2_simulator(input: &str) -> usize {
+    let (grid_data, instructions) = input.split_once("\n\n").unwrap();
+    simulate(Grid::new_wide(grid_data), instructions)
+}
+
+#[aoc(day15, part1, Shapes)]
+fn part1_shapes(input: &str) -> Result<usize, WarehouseError> {
+    let (grid_data, instructions) = input.split_once("\n\n").unwrap();
+    run_shapes(grid_data, instructions)
+}
+
+#[aoc(day15, part2, Shapes)]
+fn part2_shapes(input: &str) -> Result<usize, WarehouseError> {
+    let (grid_data, instructions) = input.split_once("\n\n").unwrap();
+    run_shapes(&widen(grid_data), instructions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SMALL: &str = "########
+#..O.O.#
+##@.O..#
+#...O..#
+#.#.O..#
+#...O..#
+#......#
+########
+
+<^^>>>vv<v>>v<<";
+
+    const WIDE: &str = "#######
+#...#.#
+#.....#
+#..OO@#
+#..O..#
+#.....#
+#######
+
+<vv<<^^<<^^";
+
+    const LONG: &str = "##########
+#..O..O.O#
+#......O.#
+#.OO..O.O#
+#..O@..O.#
+#O#..O...#
+#O..O..O.#
+#.OO.O.OO#
+#....O...#
+##########
+
+<vv>^<v^>v>^vv^v>v<>v^v<v<^vv<<<^><<><>>v<vvv<>^v^>^<<<><<v<<<v^vv^v>^
+vvv<<^>^v^^><<>>><>^<<><^vv^^<>vvv<>><^^v>^>vv<>v<<<<v<^v>^<^^>>>^<v<v
+><>vv>v^v^<>><>>>><^^>vv>v<^^^>>v^v^<^^>v^^>v^<^v>v<>>v^v^<v^v^>><v<^>
+v^>vv>^v^^vv<<vv<v^><^^v>^<vv>^v^><^vv>^<vvv^>^><v<^><^vv>^<v<^^v<v>v<
+<vv<v^>v<v<>^<<^>><<^^^^>^>v^>^^<v>^><vv<v<><^>v>><v>^^v^<v>>^^^<^<><^
+<>^v>^vvv^><<>vv<v<<v>vv^<vvv>v<v<v<^v<vv>^><v<><<vv^^><vvvvv^v><>^vv<
+^v><^v^<^v^v><v<><><^<v>v^v^<v><v<^^<vv>>v^<>>v^vv^v>v>v^vvv<><vv><<vv
+<v>>^^^<<<^^>vvv<vv^>>v^^^v>><<<><v<^v^^v^v^v>v<>v<v<^><v^v^^vv<^<v<><
+^v><>v^^<<v<vv><vv>^^>v^<>>v<>>^<vv>^>>v>v^v>>^<v><<^><v^<v^v<vv<>^v^^
+v<^<^v<^^<<^v><vv<<<<v<v>>vv>v<v>^^>>^v<v^^><vvv>>>vv^<>>>><^^v^<v^v^v";
+
+    #[test]
+    fn shapes() {
+        for input in [SMALL, WIDE, LONG] {
+            assert_eq!(part1_shapes(input).unwrap(), part1(input));
+            assert_eq!(part2_shapes(input).unwrap(), part2(input));
+        }
+    }
+
+    #[test]
+    fn letter_box_blocked_on_one_cell() {
+        let blocked = "#######\n#..#..#\n#.LL..#\n#.L...#\n#.@...#\n#######";
+        let mut warehouse = Warehouse::new(blocked).unwrap();
+        warehouse.run(&parse_instructions("^").unwrap());
+        assert_eq!(warehouse.robots, [(4, 2)]);
+        assert_eq!(warehouse.boxes, [vec![(2, 2), (3, 2), (2, 3)]]);
+        let mut warehouse = Warehouse::new(&blocked.replace("#..#", "#...")).unwrap();
+        warehouse.run(&parse_instructions("^").unwrap());
+        assert_eq!(warehouse.robots, [(3, 2)]);
+        assert_eq!(warehouse.boxes, [vec![(1, 2), (2, 2), (1, 3)]]);
+    }
+
+    #[test]
+    fn robots_in_a_row() {
+        let mut warehouse = Warehouse::new("#######\n#@@O..#\n#######").unwrap();
+        warehouse.run(&parse_instructions(">").unwrap());
+        assert_eq!(warehouse.robots, [(1, 2), (1, 3)]);
+        assert_eq!(warehouse.boxes, [vec![(1, 4)]]);
+        warehouse.run(&parse_instructions(">>").unwrap());
+        assert_eq!(warehouse.robots, [(1, 3), (1, 4)]);
+        assert_eq!(warehouse.boxes, [vec![(1, 5)]]);
+        warehouse.run(&parse_instructions("<").unwrap());
+        assert_eq!(warehouse.robots, [(1, 2), (1, 3)]);
+        assert_eq!(warehouse.sum_gps(), 105);
+    }
+
+    #[test]
+    fn unknown_instruction() {
+        let input = "#####\n#@O.#\n#####\n\n<^\nvx";
+        assert!(matches!(
+            part1_shapes(input),
+            Err(WarehouseError::UnknownInstruction('x', 3))
+        ));
+        assert!(matches!(
+            part2_shapes(input),
+            Err(WarehouseError::UnknownInstruction('x', 3))
+        ));
+    }
+
+    #[test]
+    fn simulator() {
+        assert_eq!(part1_simulator(SMALL), part1(SMALL));
+        assert_eq!(part1_simulator(SMALL), 2028);
+        assert_eq!(part2_simulator(WIDE), part2(WIDE));
+        assert_eq!(part2_simulator(WIDE), 618);
+    }
+
+    #[test]
+    fn replay() {
+        let (grid_data, instructions) = WIDE.split_once("\n\n").unwrap();
+        let mut sim = Simulator::new(Grid::new_wide(grid_data), instructions);
+        let end = sim.instructions.len();
+        let states: Vec<_> = (0..=end).map(|i| sim.state_at(i).to_string()).collect();
+        assert_eq!(
+            [&states[0], &states[6], &states[end]],
+            [
+                "##############
+##......##..##
+##..........##
+##....[][]@.##
+##....[]....##
+##..........##
+##############",
+                "##############
+##......##..##
+##...[][]...##
+##....[]....##
+##.....@....##
+##..........##
+##############",
+                "##############
+##...[].##..##
+##...@.[]...##
+##....[]....##
+##..........##
+##..........##
+##############",
+            ]
+        );
+        assert_eq!(
+            sim.log.iter().map(Move::to_string).collect::<Vec<_>>()[5..7],
+            [
+                "^: (5, 7) -> (4, 7), pushed 3 boxes",
+                "^: blocked at (4, 7)"
+            ]
+        );
+        assert_eq!(sim.state_at(0).to_string(), states[0]);
+        for (i, state) in states.iter().enumerate() {
+            assert_eq!(&sim.state_at(i).to_string(), state, "forward to {i}");
+        }
+        for (i, state) in states.iter().enumerate().rev() {
+            assert_eq!(&sim.state_at(i).to_string(), state, "back to {i}");
+        }
+    }
+}
@@ -1,6 +1,98 @@
 use aoc_runner_derive::aoc;
 use regex::Regex;
 
+const MUL: &[u8] = b"mul(";
+const DO: &[u8] = b"do()";
+const DONT: &[u8] = b"don't()";
+
+#[derive(Clone, Copy)]
+enum State {
+    Start,
+    Keyword(&'static [u8], usize),
+    Lhs(u32, bool),
+    Rhs(u32, u32, bool),
+}
+
+struct Scanner {
+    state: State,
+    conditionals: bool,
+    enabled: bool,
+    total: u64,
+}
+
+impl Scanner {
+    fn new(conditionals: bool) -> Self {
+        Scanner {
+            state: State::Start,
+            conditionals,
+            enabled: true,
+            total: 0,
+        }
+    }
+
+    fn start(b: u8) -> State {
+        match b {
+            b'm' => State::Keyword(MUL, 1),
+            b'd' => State::Keyword(DO, 1),
+            _ => State::Start,
+        }
+    }
+
+    fn keyword_done(&mut self, keyword: &[u8]) -> State {
+        match keyword {
+            MUL => return State::Lhs(0, false),
+            DO if self.conditionals => self.enabled = true,
+            DONT if self.conditionals => self.enabled = false,
+            _ => {}
+        }
+        State::Start
+    }
+
+    fn step(&mut self, b: u8) {
+        self.state = match self.state {
+            State::Start => Self::start(b),
+            State::Keyword(keyword, n) if keyword[n] == b => {
+                if n + 1 == keyword.len() {
+                    self.keyword_done(keyword)
+                } else {
+                    State::Keyword(keyword, n + 1)
+                }
+            }
+            State::Keyword(DO, 2) if b == b'n' => State::Keyword(DONT, 3),
+            State::Lhs(a, _) if b.is_ascii_digit() => match accumulate(a, b) {
+                Some(a) => State::Lhs(a, true),
+                None => State::Start,
+            },
+            State::Lhs(a, true) if b == b',' => State::Rhs(a, 0, false),
+            State::Rhs(a, c, _) if b.is_ascii_digit() => match accumulate(c, b) {
+                Some(c) => State::Rhs(a, c, true),
+                None => State::Start,
+            },
+            State::Rhs(a, c, true) if b == b')' => {
+                if self.enabled {
+                    self.total += a as u64 * c as u64;
+                }
+                State::Start
+            }
+            _ => Self::start(b),
+        }
+    }
+
+    fn feed(&mut self, chunk: &[u8]) {
+        chunk.iter().for_each(|&b| self.step(b));
+    }
+}
+
+fn accumulate(value: u32, digit: u8) -> Option<u32> {
+    value.checked_mul(10)?.checked_add((digit - b'0') as u32)
+}
+
+fn scan<'a>(chunks: impl IntoIterator<Item = &'a [u8]>, conditionals: bool) -> u64 {
+    let mut scanner = Scanner::new(conditionals);
+    chunks.into_iter().for_each(|chunk| scanner.feed(chunk));
+    scanner.total
+}
+
 #[aoc(day3, part1)]
 fn part1(input: &str) -> u32 {
     let re = Regex::new(r"mul\(([0-9]+),([0-9]+)\)").unwrap();
@@ -10,6 +102,11 @@ fn part1(input: &str) -> u32 {
         .sum()
 }
 
+#[aoc(day3, part1, Scanner)]
+fn part1_scanner(input: &[u8]) -> u64 {
+    scan([input], false)
+}
+
 #[aoc(day3, part2)]
 fn part2(input: &str) -> u32 {
     part1(
@@ -20,3 +117,42 @@ fn part2(input: &str) -> u32 {
             .concat(),
     )
 }
+
+#[aoc(day3, part2, Scanner)]
+fn part2_scanner(input: &[u8]) -> u64 {
+    scan([input], true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE1: &[u8] =
+        b"xmul(2,4)%&mul[3,7]!@^do_not_mul(5,5)+mul(32,64]then(mul(11,8)mul(8,5))";
+    const EXAMPLE2: &[u8] =
+        b"xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo()?mul(8,5))";
+
+    #[test]
+    fn examples() {
+        assert_eq!(part1_scanner(EXAMPLE1), 161);
+        assert_eq!(part2_scanner(EXAMPLE2), 48);
+    }
+
+    #[test]
+    fn split_chunks() {
+        for i in 0..=EXAMPLE1.len() {
+            assert_eq!(scan([&EXAMPLE1[..i], &EXAMPLE1[i..]], false), 161);
+        }
+        for i in 0..=EXAMPLE2.len() {
+            assert_eq!(scan([&EXAMPLE2[..i], &EXAMPLE2[i..]], true), 48);
+        }
+    }
+
+    #[test]
+    fn large_operands() {
+        assert_eq!(
+            scan([b"mul(100000,100000)".as_slice()], false),
+            10_000_000_000
+        );
+    }
+}
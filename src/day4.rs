@@ -1,3 +1,4 @@
+use aho_corasick::AhoCorasick;
 use aoc_runner_derive::{aoc, aoc_generator};
 use itertools::Itertools;
 
@@ -12,6 +13,53 @@ const DIRS: [(i32, i32); 8] = [
     (1, -1),
 ];
 
+type WordMatch = (usize, (usize, usize), (i32, i32));
+type ShapeMatch = ((usize, usize), usize);
+
+struct Shape {
+    cells: Vec<((i32, i32), char)>,
+}
+
+impl Shape {
+    // '.' cells in the template are wildcards
+    fn new(template: &str) -> Self {
+        let cells = template
+            .lines()
+            .enumerate()
+            .flat_map(|(i, l)| {
+                l.chars()
+                    .enumerate()
+                    .filter(|&(_, c)| c != '.')
+                    .map(move |(j, c)| ((i as i32, j as i32), c))
+            })
+            .collect();
+        Shape { cells }
+    }
+
+    fn rotate(&self) -> Self {
+        let mut cells: Vec<_> = self.cells.iter().map(|&((i, j), c)| ((j, -i), c)).collect();
+        let min_i = cells.iter().map(|&((i, _), _)| i).min().unwrap_or(0);
+        let min_j = cells.iter().map(|&((_, j), _)| j).min().unwrap_or(0);
+        cells.iter_mut().for_each(|((i, j), _)| {
+            *i -= min_i;
+            *j -= min_j;
+        });
+        cells.sort();
+        Shape { cells }
+    }
+
+    fn rotations(&self) -> Vec<Shape> {
+        let mut rotations = vec![self.rotate().rotate().rotate().rotate()];
+        for _ in 0..3 {
+            let next = rotations.last().unwrap().rotate();
+            if rotations.iter().all(|r| r.cells != next.cells) {
+                rotations.push(next);
+            }
+        }
+        rotations
+    }
+}
+
 struct Grid {
     data: Vec<Vec<char>>,
     height: usize,
@@ -28,6 +76,102 @@ impl Grid {
         }
     }
 
+    fn get(&self, (i, j): (i32, i32), wrap: bool) -> Option<char> {
+        let (i, j) = if wrap {
+            (
+                i.rem_euclid(self.height as i32),
+                j.rem_euclid(self.width as i32),
+            )
+        } else {
+            (i, j)
+        };
+        self.data
+            .get(usize::try_from(i).ok()?)?
+            .get(usize::try_from(j).ok()?)
+            .copied()
+    }
+
+    fn has_word_in_dir(
+        &self,
+        word: &str,
+        start: (usize, usize),
+        dir: (i32, i32),
+        wrap: bool,
+    ) -> bool {
+        let (i, j) = (start.0 as i32, start.1 as i32);
+        let (di, dj) = dir;
+        word.chars()
+            .zip(0..)
+            .all(|(c, k)| self.get((i + k * di, j + k * dj), wrap) == Some(c))
+    }
+
+    fn find_words(&self, words: &[&str], wrap: bool) -> Vec<WordMatch> {
+        (0..self.height)
+            .cartesian_product(0..self.width)
+            .cartesian_product(DIRS)
+            .flat_map(|(start, dir)| {
+                words
+                    .iter()
+                    .enumerate()
+                    .filter(move |&(_, word)| self.has_word_in_dir(word, start, dir, wrap))
+                    .map(move |(k, _)| (k, start, dir))
+            })
+            .collect()
+    }
+
+    fn has_shape_at(&self, shape: &Shape, (i, j): (usize, usize)) -> bool {
+        shape
+            .cells
+            .iter()
+            .all(|&((di, dj), c)| self.get((i as i32 + di, j as i32 + dj), false) == Some(c))
+    }
+
+    fn find_shape(&self, shape: &Shape) -> Vec<ShapeMatch> {
+        let rotations = shape.rotations();
+        (0..self.height)
+            .cartesian_product(0..self.width)
+            .flat_map(|start| {
+                rotations
+                    .iter()
+                    .enumerate()
+                    .filter(move |&(_, r)| self.has_shape_at(r, start))
+                    .map(move |(k, _)| (start, k))
+            })
+            .collect()
+    }
+
+    // Rows, columns, diagonals and anti-diagonals, each read in one direction
+    fn lines(&self) -> Vec<String> {
+        let (h, w) = (self.height as i32, self.width as i32);
+        let walk = |start: (i32, i32), (di, dj): (i32, i32)| -> String {
+            (0..)
+                .map_while(|k| self.get((start.0 + k * di, start.1 + k * dj), false))
+                .collect()
+        };
+        let rows = (0..h).map(|i| walk((i, 0), (0, 1)));
+        let cols = (0..w).map(|j| walk((0, j), (1, 0)));
+        let diags = (1 - w..h).map(|d| walk((d.max(0), (-d).max(0)), (1, 1)));
+        let anti_diags = (0..h + w - 1).map(|d| walk((d.min(h - 1), (d - h + 1).max(0)), (-1, 1)));
+        rows.chain(cols).chain(diags).chain(anti_diags).collect()
+    }
+
+    fn count_words(&self, words: &[&str]) -> Vec<usize> {
+        let reversed = words.iter().map(|w| w.chars().rev().collect::<String>());
+        let patterns: Vec<_> = words
+            .iter()
+            .map(|w| w.to_string())
+            .chain(reversed)
+            .collect();
+        let automaton = AhoCorasick::new(&patterns).unwrap();
+        let mut counts = vec![0; words.len()];
+        for line in self.lines() {
+            for m in automaton.find_overlapping_iter(&line) {
+                counts[m.pattern().as_usize() % words.len()] += 1;
+            }
+        }
+        counts
+    }
+
     fn has_xmas_in_dir(&self, start: (usize, usize), dir: (i32, i32)) -> bool {
         let (i, j) = start;
         let (di, dj) = dir;
@@ -69,6 +213,16 @@ fn part1(input: &Grid) -> usize {
         .sum()
 }
 
+#[aoc(day4, part1, Search)]
+fn part1_search(input: &Grid) -> usize {
+    input.find_words(&["XMAS"], false).len()
+}
+
+#[aoc(day4, part1, AhoCorasick)]
+fn part1_aho_corasick(input: &Grid) -> usize {
+    input.count_words(&["XMAS"])[0]
+}
+
 #[aoc(day4, part2)]
 fn part2(input: &Grid) -> usize {
     (1..input.height - 1)
@@ -76,3 +230,60 @@ fn part2(input: &Grid) -> usize {
         .filter(|&start| input.has_cross_mas(start))
         .count()
 }
+
+#[aoc(day4, part2, Search)]
+fn part2_search(input: &Grid) -> usize {
+    input.find_shape(&Shape::new("M.S\n.A.\nM.S")).len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "MMMSXXMASM
+MSAMXMSMSA
+AMXSXMAAMM
+MSAMASMSMX
+XMASAMXAMM
+XXAMMXXAMA
+SMSMSASXSS
+SAXAMASAAA
+MAMMMXMMMM
+MXMXAXMASX";
+
+    #[test]
+    fn example() {
+        let grid = input_generator(EXAMPLE);
+        assert_eq!(part1(&grid), 18);
+        assert_eq!(part1_search(&grid), 18);
+        assert_eq!(part1_aho_corasick(&grid), 18);
+        assert_eq!(part2(&grid), 9);
+        assert_eq!(part2_search(&grid), 9);
+    }
+
+    #[test]
+    fn wrap_around() {
+        let grid = Grid::new("ASXM\n....\n....\n....");
+        assert_eq!(grid.find_words(&["XMAS"], false), vec![]);
+        assert_eq!(grid.find_words(&["XMAS"], true), vec![(0, (0, 2), (0, 1))]);
+    }
+
+    #[test]
+    fn symmetric_shapes() {
+        let grid = input_generator(EXAMPLE);
+        assert_eq!(Shape::new("M.S\n.A.\nM.S").rotations().len(), 4);
+        assert_eq!(Shape::new("A").rotations().len(), 1);
+        let a_count = EXAMPLE.chars().filter(|&c| c == 'A').count();
+        assert_eq!(grid.find_shape(&Shape::new("A")).len(), a_count);
+    }
+
+    #[test]
+    fn counts_match_search() {
+        let grid = input_generator(EXAMPLE);
+        let words = ["XMAS", "MAM", "SAS", "A", "AA", "XM"];
+        let counts = grid.count_words(&words);
+        for (k, &word) in words.iter().enumerate() {
+            assert_eq!(counts[k], grid.find_words(&[word], false).len(), "{word}");
+        }
+    }
+}
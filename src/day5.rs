@@ -1,6 +1,54 @@
 use aoc_runner_derive::aoc;
+use itertools::Itertools;
 use std::cmp::Ordering;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+
+#[derive(Debug)]
+enum SortError {
+    Unordered(u32, u32),
+    Cycle(Vec<u32>),
+}
+
+impl fmt::Display for SortError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SortError::Unordered(a, b) => write!(f, "no rule orders pages {a} and {b}"),
+            SortError::Cycle(pages) => {
+                write!(f, "rules form a cycle: {}", pages.iter().join(" -> "))
+            }
+        }
+    }
+}
+
+impl Error for SortError {}
+
+struct Diagnosis {
+    update: Vec<u32>,
+    violations: Vec<((u32, u32), (usize, usize))>,
+    moves: Vec<(u32, usize, usize)>,
+    fixed: Vec<u32>,
+}
+
+impl fmt::Display for Diagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Update {}", self.update.iter().join(","))?;
+        if self.violations.is_empty() {
+            return writeln!(f, "  valid");
+        }
+        for ((a, b), (i, j)) in &self.violations {
+            writeln!(f, "  rule {a}|{b} violated: {b} at {j} before {a} at {i}")?;
+        }
+        for &(page, from, to) in &self.moves {
+            match to {
+                0 => writeln!(f, "  move {page} from {from} to the front")?,
+                _ => writeln!(f, "  move {page} from {from} after {}", self.fixed[to - 1])?,
+            }
+        }
+        writeln!(f, "  fixed: {}", self.fixed.iter().join(","))
+    }
+}
 
 struct PageOrder {
     pairs: HashSet<(u32, u32)>,
@@ -27,6 +75,90 @@ impl PageOrder {
         }
         Ordering::Greater
     }
+
+    fn sort(&self, update: &[u32]) -> Result<Vec<u32>, SortError> {
+        let mut preds: HashMap<_, Vec<_>> = update.iter().map(|&p| (p, Vec::new())).collect();
+        for (&a, &b) in update.iter().cartesian_product(update) {
+            if self.pairs.contains(&(a, b)) {
+                preds.get_mut(&b).unwrap().push(a);
+            }
+        }
+        let mut sorted = Vec::new();
+        while !preds.is_empty() {
+            let ready: Vec<_> = preds
+                .iter()
+                .filter(|(_, p)| p.is_empty())
+                .map(|(&page, _)| page)
+                .collect();
+            match ready[..] {
+                [] => return Err(SortError::Cycle(find_cycle(&preds))),
+                [page] => {
+                    preds.remove(&page);
+                    preds.values_mut().for_each(|p| p.retain(|&a| a != page));
+                    sorted.push(page);
+                }
+                [a, b, ..] => return Err(SortError::Unordered(a.min(b), a.max(b))),
+            }
+        }
+        Ok(sorted)
+    }
+
+    fn diagnose(&self, update: &[u32]) -> Result<Diagnosis, SortError> {
+        let violations = (0..update.len())
+            .tuple_combinations()
+            .filter(|&(j, i)| self.pairs.contains(&(update[i], update[j])))
+            .map(|(j, i)| ((update[i], update[j]), (i, j)))
+            .collect();
+        let fixed = self.sort(update)?;
+        let ranks: Vec<_> = update
+            .iter()
+            .map(|p| fixed.iter().position(|q| q == p).unwrap())
+            .collect();
+        let kept = longest_increasing(&ranks);
+        let moves = (0..update.len())
+            .filter(|i| !kept.contains(i))
+            .map(|i| (update[i], i, ranks[i]))
+            .collect();
+        Ok(Diagnosis {
+            update: update.to_vec(),
+            violations,
+            moves,
+            fixed,
+        })
+    }
+}
+
+// Indices of a longest strictly increasing subsequence
+fn longest_increasing(values: &[usize]) -> Vec<usize> {
+    let mut best: Vec<(usize, Option<usize>)> = Vec::new();
+    for i in 0..values.len() {
+        let prev = (0..i)
+            .filter(|&j| values[j] < values[i])
+            .max_by_key(|&j| best[j].0);
+        best.push((prev.map_or(1, |j| best[j].0 + 1), prev));
+    }
+    let mut current = (0..values.len()).max_by_key(|&i| best[i].0);
+    let mut res = Vec::new();
+    while let Some(i) = current {
+        res.push(i);
+        current = best[i].1;
+    }
+    res.reverse();
+    res
+}
+
+// Every remaining page has a remaining predecessor, so walking back must loop
+fn find_cycle(preds: &HashMap<u32, Vec<u32>>) -> Vec<u32> {
+    let mut path = vec![*preds.keys().min().unwrap()];
+    loop {
+        let page = preds[path.last().unwrap()][0];
+        if let Some(idx) = path.iter().position(|&p| p == page) {
+            let mut cycle = path.split_off(idx);
+            cycle.reverse();
+            return cycle;
+        }
+        path.push(page);
+    }
 }
 
 fn parse_update(input: &str) -> Vec<u32> {
@@ -45,6 +177,19 @@ fn part1(input: &str) -> u32 {
         .sum()
 }
 
+#[aoc(day5, part1, TopoSort)]
+fn part1_topo_sort(input: &str) -> Result<u32, SortError> {
+    let (rules, updates) = input.split_once("\n\n").unwrap();
+    let order = PageOrder::new(rules);
+    let mut res = 0;
+    for update in updates.lines().map(parse_update) {
+        if order.sort(&update)? == update {
+            res += update[(update.len() - 1) / 2];
+        }
+    }
+    Ok(res)
+}
+
 #[aoc(day5, part2)]
 fn part2(input: &str) -> u32 {
     let (rules, updates) = input.split_once("\n\n").unwrap();
@@ -59,3 +204,94 @@ fn part2(input: &str) -> u32 {
         })
         .sum()
 }
+
+#[aoc(day5, part2, TopoSort)]
+fn part2_topo_sort(input: &str) -> Result<u32, SortError> {
+    let (rules, updates) = input.split_once("\n\n").unwrap();
+    let order = PageOrder::new(rules);
+    let mut res = 0;
+    for update in updates.lines().map(parse_update) {
+        let sorted = order.sort(&update)?;
+        if sorted != update {
+            res += sorted[(sorted.len() - 1) / 2];
+        }
+    }
+    Ok(res)
+}
+
+#[aoc(day5, part2, Report)]
+fn part2_report(input: &str) -> Result<u32, SortError> {
+    let (rules, updates) = input.split_once("\n\n").unwrap();
+    let order = PageOrder::new(rules);
+    let mut res = 0;
+    for update in updates.lines().map(parse_update) {
+        let diagnosis = order.diagnose(&update)?;
+        if !diagnosis.violations.is_empty() {
+            res += diagnosis.fixed[(diagnosis.fixed.len() - 1) / 2];
+        }
+    }
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "47|53
+97|13
+97|61
+97|47
+75|29
+61|13
+75|53
+29|13
+97|29
+53|29
+61|53
+97|53
+61|29
+47|13
+75|47
+97|75
+47|61
+75|61
+47|29
+75|13
+53|13
+
+75,47,61,53,29
+97,61,53,29,13
+75,29,13
+75,97,47,61,53
+61,13,29
+97,13,75,29,47";
+
+    #[test]
+    fn example() {
+        assert_eq!(part1(EXAMPLE), 143);
+        assert_eq!(part2(EXAMPLE), 123);
+        assert_eq!(part2_topo_sort(EXAMPLE).unwrap(), 123);
+        assert_eq!(part2_report(EXAMPLE).unwrap(), 123);
+    }
+
+    #[test]
+    fn diagnose() {
+        let order = PageOrder::new(EXAMPLE.split_once("\n\n").unwrap().0);
+        let diagnosis = order.diagnose(&[97, 13, 75, 29, 47]).unwrap();
+        assert_eq!(diagnosis.violations.len(), 4);
+        assert_eq!(diagnosis.moves.len(), 2);
+        assert_eq!(diagnosis.fixed, vec![97, 75, 47, 29, 13]);
+        assert_eq!(
+            diagnosis.to_string(),
+            "Update 97,13,75,29,47
+  rule 75|13 violated: 13 at 1 before 75 at 2
+  rule 29|13 violated: 13 at 1 before 29 at 3
+  rule 47|13 violated: 13 at 1 before 47 at 4
+  rule 47|29 violated: 29 at 3 before 47 at 4
+  move 13 from 1 after 29
+  move 29 from 3 after 47
+  fixed: 97,75,47,29,13
+"
+        );
+    }
+}
@@ -1,5 +1,11 @@
 use aoc_runner_derive::aoc;
-use std::collections::HashSet;
+use itertools::Itertools;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+type State = ((usize, usize), Direction);
 
 #[derive(Hash, PartialEq, Eq, Clone, Copy)]
 enum Direction {
@@ -18,23 +24,142 @@ fn turn(dir: Direction) -> Direction {
     }
 }
 
+#[derive(Clone, Copy)]
+enum TurnRule {
+    Left,
+    Right,
+    Reverse,
+}
+
+impl TurnRule {
+    fn apply(self, dir: Direction) -> Direction {
+        match self {
+            TurnRule::Right => turn(dir),
+            TurnRule::Reverse => turn(turn(dir)),
+            TurnRule::Left => turn(turn(turn(dir))),
+        }
+    }
+}
+
+struct Simulation {
+    visited: Vec<HashSet<(usize, usize)>>,
+    collisions: usize,
+    loops: bool,
+}
+
+impl fmt::Display for Simulation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let counts = self.visited.iter().map(HashSet::len).join(", ");
+        write!(
+            f,
+            "visited [{counts}], {} collisions, loops: {}",
+            self.collisions, self.loops
+        )
+    }
+}
+
+const DIRECTIONS: [Direction; 4] = [
+    Direction::Up,
+    Direction::Right,
+    Direction::Down,
+    Direction::Left,
+];
+
+// For each direction and cell, the cell where the guard stops before an obstacle
+struct JumpTable {
+    width: usize,
+    stops: [Vec<Option<(usize, usize)>>; 4],
+}
+
+impl JumpTable {
+    fn new(grid: &Grid) -> Self {
+        let (height, width) = (grid.height, grid.width);
+        let mut stops = [(); 4].map(|_| vec![None; height * width]);
+        for dir in DIRECTIONS {
+            let (outer, inner) = match dir {
+                Direction::Up | Direction::Down => (width, height),
+                Direction::Right | Direction::Left => (height, width),
+            };
+            for a in 0..outer {
+                let mut stop = None;
+                for b in 0..inner {
+                    let b = match dir {
+                        Direction::Up | Direction::Left => b,
+                        Direction::Right | Direction::Down => inner - 1 - b,
+                    };
+                    let pos = match dir {
+                        Direction::Up | Direction::Down => (b, a),
+                        Direction::Right | Direction::Left => (a, b),
+                    };
+                    if grid.obstacles.contains(&pos) {
+                        stop = grid.step(pos, turn(turn(dir)));
+                    } else {
+                        stops[dir as usize][pos.0 * width + pos.1] = stop;
+                    }
+                }
+            }
+        }
+        JumpTable { width, stops }
+    }
+
+    fn jump(
+        &self,
+        (i, j): (usize, usize),
+        dir: Direction,
+        (oi, oj): (usize, usize),
+    ) -> Option<(usize, usize)> {
+        let stop = self.stops[dir as usize][i * self.width + j];
+        match dir {
+            Direction::Up if oj == j && oi < i => stop.max(Some((oi + 1, j))),
+            Direction::Right if oi == i && oj > j => {
+                stop.min(Some((i, oj - 1))).or(Some((i, oj - 1)))
+            }
+            Direction::Down if oj == j && oi > i => {
+                stop.min(Some((oi - 1, j))).or(Some((oi - 1, j)))
+            }
+            Direction::Left if oi == i && oj < j => stop.max(Some((i, oj + 1))),
+            _ => stop,
+        }
+    }
+
+    fn has_loop(&self, pos: (usize, usize), dir: Direction, obstacle: (usize, usize)) -> bool {
+        let mut visited = vec![0u64; (self.stops[0].len() * 4).div_ceil(64)];
+        let (mut pos, mut dir) = (pos, dir);
+        while let Some(stop) = self.jump(pos, dir, obstacle) {
+            (pos, dir) = (stop, turn(dir));
+            let idx = (pos.0 * self.width + pos.1) * 4 + dir as usize;
+            if visited[idx / 64] & (1 << (idx % 64)) != 0 {
+                return true;
+            }
+            visited[idx / 64] |= 1 << (idx % 64);
+        }
+        false
+    }
+}
+
 struct Grid {
     height: usize,
     width: usize,
     obstacles: HashSet<(usize, usize)>,
-    start: (usize, usize),
+    // The first guard, used by the single guard walks, or the top-left
+    // corner facing up when the map has none
+    start: State,
+    guards: Vec<State>,
 }
 
 impl Grid {
     fn new(input: &str) -> Self {
         let height = input.lines().count();
         let width = input.lines().next().unwrap().len();
-        let mut start = (0, 0);
         let mut obstacles = HashSet::new();
+        let mut guards = Vec::new();
         for (i, l) in input.lines().enumerate() {
             for (j, c) in l.chars().enumerate() {
                 match c {
-                    '^' => start = (i, j),
+                    '^' => guards.push(((i, j), Direction::Up)),
+                    '>' => guards.push(((i, j), Direction::Right)),
+                    'v' => guards.push(((i, j), Direction::Down)),
+                    '<' => guards.push(((i, j), Direction::Left)),
                     '#' => {
                         obstacles.insert((i, j));
                     }
@@ -46,7 +171,18 @@ impl Grid {
             height,
             width,
             obstacles,
-            start,
+            start: guards.first().copied().unwrap_or(((0, 0), Direction::Up)),
+            guards,
+        }
+    }
+
+    fn step(&self, (i, j): (usize, usize), dir: Direction) -> Option<(usize, usize)> {
+        match dir {
+            Direction::Up if i > 0 => Some((i - 1, j)),
+            Direction::Right if j < self.width - 1 => Some((i, j + 1)),
+            Direction::Down if i < self.height - 1 => Some((i + 1, j)),
+            Direction::Left if j > 0 => Some((i, j - 1)),
+            _ => None,
         }
     }
 
@@ -83,12 +219,140 @@ impl Grid {
         }
         false
     }
+
+    // Ordered guard states, with the index where the path starts cycling if it loops
+    fn walk(&self) -> (Vec<State>, Option<usize>) {
+        let mut current = Some(self.start);
+        let mut path = Vec::new();
+        let mut seen = HashMap::new();
+        while let Some(state) = current {
+            if let Some(&idx) = seen.get(&state) {
+                return (path, Some(idx));
+            }
+            seen.insert(state, path.len());
+            path.push(state);
+            current = self.advance(state.0, state.1);
+        }
+        (path, None)
+    }
+
+    fn turn_points(path: &[State]) -> Vec<(usize, usize)> {
+        path.iter()
+            .tuple_windows()
+            .filter(|((_, d1), (_, d2))| d1 != d2)
+            .map(|((p, _), _)| *p)
+            .collect()
+    }
+
+    fn loop_obstacles(&mut self) -> Vec<((usize, usize), Vec<State>)> {
+        let candidates: Vec<_> = self.walk().0.into_iter().map(|(p, _)| p).unique().collect();
+        let mut res = Vec::new();
+        for obstacle in candidates.into_iter().filter(|&p| p != self.start.0) {
+            self.obstacles.insert(obstacle);
+            if let (mut path, Some(idx)) = self.walk() {
+                res.push((obstacle, path.split_off(idx)));
+            }
+            self.obstacles.remove(&obstacle);
+        }
+        res
+    }
+
+    // Guards move simultaneously, one step or one turn per tick, and treat
+    // each other (and cells claimed by several guards) as obstacles
+    fn simulate(&self, rule: TurnRule) -> Simulation {
+        let mut guards: Vec<_> = self.guards.iter().copied().map(Some).collect();
+        let mut visited: Vec<_> = self
+            .guards
+            .iter()
+            .map(|&(p, _)| HashSet::from([p]))
+            .collect();
+        let mut collisions = 0;
+        let mut seen = HashSet::new();
+        while guards.iter().any(Option::is_some) {
+            if !seen.insert(guards.clone()) {
+                return Simulation {
+                    visited,
+                    collisions,
+                    loops: true,
+                };
+            }
+            let occupied: HashSet<_> = guards.iter().flatten().map(|&(p, _)| p).collect();
+            let targets: Vec<_> = guards
+                .iter()
+                .map(|g| g.and_then(|(p, d)| self.step(p, d)))
+                .collect();
+            let claims = targets.iter().flatten().counts();
+            for (k, guard) in guards.iter_mut().enumerate() {
+                let Some((pos, dir)) = *guard else {
+                    continue;
+                };
+                *guard = match targets[k] {
+                    None => None,
+                    Some(next) if self.obstacles.contains(&next) => Some((pos, rule.apply(dir))),
+                    Some(next) if occupied.contains(&next) || claims[&next] > 1 => {
+                        collisions += 1;
+                        Some((pos, rule.apply(dir)))
+                    }
+                    Some(next) => {
+                        visited[k].insert(next);
+                        Some((next, dir))
+                    }
+                };
+            }
+        }
+        Simulation {
+            visited,
+            collisions,
+            loops: false,
+        }
+    }
+
+    fn render(&self, path: &[State], new_obstacles: &[(usize, usize)]) -> String {
+        let mut marks: HashMap<_, (bool, bool)> = HashMap::new();
+        for &(p, d) in path {
+            let mark = marks.entry(p).or_default();
+            match d {
+                Direction::Up | Direction::Down => mark.0 = true,
+                Direction::Right | Direction::Left => mark.1 = true,
+            }
+        }
+        for p in Self::turn_points(path) {
+            marks.insert(p, (true, true));
+        }
+        (0..self.height)
+            .map(|i| {
+                (0..self.width)
+                    .map(|j| match marks.get(&(i, j)) {
+                        _ if self.obstacles.contains(&(i, j)) => '#',
+                        _ if new_obstacles.contains(&(i, j)) => 'O',
+                        _ if (i, j) == self.start.0 => match self.start.1 {
+                            Direction::Up => '^',
+                            Direction::Right => '>',
+                            Direction::Down => 'v',
+                            Direction::Left => '<',
+                        },
+                        Some((true, true)) => '+',
+                        Some((true, false)) => '|',
+                        Some((false, true)) => '-',
+                        _ => '.',
+                    })
+                    .collect::<String>()
+            })
+            .join("\n")
+    }
+}
+
+// The map with the first guard's walk drawn over it
+impl fmt::Display for Grid {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.render(&self.walk().0, &[]))
+    }
 }
 
 #[aoc(day6, part1)]
 fn part1(input: &str) -> usize {
     let grid = Grid::new(input);
-    let mut current = Some((grid.start, Direction::Up));
+    let mut current = Some(grid.start);
     let mut visited = HashSet::new();
     while let Some((p, d)) = current {
         visited.insert(p);
@@ -100,8 +364,7 @@ fn part1(input: &str) -> usize {
 #[aoc(day6, part2)]
 fn part2(input: &str) -> usize {
     let mut grid = Grid::new(input);
-    let mut pos = grid.start;
-    let mut dir = Direction::Up;
+    let (mut pos, mut dir) = grid.start;
     let mut next = grid.advance(pos, dir);
     let mut visited = HashSet::from([pos]);
     let mut count = 0;
@@ -117,3 +380,227 @@ fn part2(input: &str) -> usize {
     }
     count
 }
+
+#[aoc(day6, part2, JumpTable)]
+fn part2_jump_table(input: &str) -> usize {
+    let grid = Grid::new(input);
+    let table = JumpTable::new(&grid);
+    let (mut pos, mut dir) = grid.start;
+    let mut next = grid.advance(pos, dir);
+    let mut visited = HashSet::from([pos]);
+    let mut count = 0;
+    while let Some((next_pos, next_dir)) = next {
+        if visited.insert(next_pos) && table.has_loop(pos, dir, next_pos) {
+            count += 1;
+        }
+        (pos, dir) = (next_pos, next_dir);
+        next = grid.advance(pos, dir);
+    }
+    count
+}
+
+#[aoc(day6, part1, Path)]
+fn part1_path(input: &str) -> usize {
+    let grid = Grid::new(input);
+    let (path, _) = grid.walk();
+    path.into_iter().map(|(p, _)| p).unique().count()
+}
+
+#[aoc(day6, part2, Path)]
+fn part2_path(input: &str) -> usize {
+    Grid::new(input).loop_obstacles().len()
+}
+
+fn guards_visited(input: &str, rule: TurnRule) -> usize {
+    let sim = Grid::new(input).simulate(rule);
+    sim.visited.iter().flatten().unique().count()
+}
+
+#[aoc(day6, part1, Guards)]
+fn part1_guards(input: &str) -> usize {
+    guards_visited(input, TurnRule::Right)
+}
+
+#[aoc(day6, part1, GuardsLeft)]
+fn part1_guards_left(input: &str) -> usize {
+    guards_visited(input, TurnRule::Left)
+}
+
+#[aoc(day6, part1, GuardsReverse)]
+fn part1_guards_reverse(input: &str) -> usize {
+    guards_visited(input, TurnRule::Reverse)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Lcg;
+
+    const EXAMPLE: &str = "....#.....
+.........#
+..........
+..#.......
+.......#..
+..........
+.#..^.....
+........#.
+#.........
+......#...";
+
+    // Random maps the guard leaves, with no free cell next to three or more
+    // obstacles so placing one more obstacle can never box the guard in
+    fn random_map(next: &mut impl FnMut(u64) -> u64) -> String {
+        loop {
+            let (height, width) = (8 + next(16) as usize, 8 + next(16) as usize);
+            let mut rows: Vec<Vec<_>> = (0..height)
+                .map(|_| {
+                    (0..width)
+                        .map(|_| if next(9) == 0 { '#' } else { '.' })
+                        .collect()
+                })
+                .collect();
+            let (i, j) = (next(height as u64) as usize, next(width as u64) as usize);
+            rows[i][j] = ['^', '>', 'v', '<'][next(4) as usize];
+            let blocked = |i: usize, j: usize| {
+                [(0, 1), (2, 1), (1, 0), (1, 2)]
+                    .iter()
+                    .filter(|&&(di, dj)| {
+                        (i + di)
+                            .checked_sub(1)
+                            .zip((j + dj).checked_sub(1))
+                            .and_then(|(i, j)| rows.get(i)?.get(j))
+                            == Some(&'#')
+                    })
+                    .count()
+            };
+            let boxed = (0..height)
+                .cartesian_product(0..width)
+                .any(|(i, j)| rows[i][j] != '#' && blocked(i, j) >= 3);
+            let map = rows.iter().map(|r| r.iter().collect::<String>()).join("\n");
+            if !boxed && Grid::new(&map).walk().1.is_none() {
+                return map;
+            }
+        }
+    }
+
+    #[test]
+    fn example() {
+        assert_eq!(part1(EXAMPLE), 41);
+        assert_eq!(part2(EXAMPLE), 6);
+        assert_eq!(part2_jump_table(EXAMPLE), 6);
+        assert_eq!(part1_path(EXAMPLE), 41);
+        assert_eq!(part2_path(EXAMPLE), 6);
+    }
+
+    #[test]
+    fn paths() {
+        let mut grid = Grid::new(EXAMPLE);
+        let (path, cycle) = grid.walk();
+        assert_eq!(cycle, None);
+        assert_eq!(path.iter().map(|&(p, _)| p).unique().count(), 41);
+        let loops = grid.loop_obstacles();
+        let obstacles: Vec<_> = loops.iter().map(|&(p, _)| p).sorted().collect();
+        assert_eq!(obstacles, [(6, 3), (7, 6), (7, 7), (8, 1), (8, 3), (9, 7)]);
+        let (_, cycle) = &loops[0];
+        assert_eq!(
+            Grid::turn_points(&[&cycle[..], &cycle[..1]].concat()),
+            [(1, 4), (1, 8), (6, 8), (6, 4)]
+        );
+    }
+
+    #[test]
+    fn render() {
+        let mut grid = Grid::new(EXAMPLE);
+        let loops = grid.loop_obstacles();
+        let pictures = [
+            (
+                (6, 3),
+                "....#.....
+....+---+#
+....|...|.
+..#.|...|.
+....|..#|.
+....|...|.
+.#.O^---+.
+........#.
+#.........
+......#...",
+            ),
+            (
+                (7, 6),
+                "....#.....
+....+---+#
+....|...|.
+..#.|...|.
+..+-+-+#|.
+..|.|.|.|.
+.#+-^-+-+.
+......O.#.
+#.........
+......#...",
+            ),
+        ];
+        for (obstacle, picture) in pictures {
+            grid.obstacles.insert(obstacle);
+            let (path, _) = grid.walk();
+            grid.obstacles.remove(&obstacle);
+            assert!(loops.iter().any(|&(p, _)| p == obstacle));
+            assert_eq!(grid.render(&path, &[obstacle]), picture);
+        }
+    }
+
+    #[test]
+    fn simulate() {
+        let cases = [
+            (EXAMPLE, "visited [41], 0 collisions, loops: false"),
+            (
+                "....\n>..<\n....",
+                "visited [3, 3], 2 collisions, loops: false",
+            ),
+            (">.<", "visited [1, 1], 2 collisions, loops: false"),
+            (
+                ".#..\n.^.#\n#...\n..#.",
+                "visited [4], 0 collisions, loops: true",
+            ),
+            ("..\n..", "visited [], 0 collisions, loops: false"),
+        ];
+        for (map, summary) in cases {
+            let sim = Grid::new(map).simulate(TurnRule::Right);
+            assert_eq!(sim.to_string(), summary, "\n{map}");
+        }
+        assert_eq!(part1_guards(EXAMPLE), 41);
+    }
+
+    #[test]
+    fn no_guard() {
+        assert_eq!(part1("..#\n..."), 1);
+        assert_eq!(part1_guards("..#\n..."), 0);
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(
+            Grid::new(EXAMPLE).to_string(),
+            "....#.....
+....+---+#
+....|...|.
+..#.|...|.
+..+-+-+#|.
+..|.|.|.|.
+.#+-^-+-+.
+.+----++#.
+#+----+|..
+......#|.."
+        );
+    }
+
+    #[test]
+    fn jump_table_matches_walk() {
+        let mut rng = Lcg::new(6);
+        let mut next = |m: u64| rng.below(m);
+        for _ in 0..300 {
+            let map = random_map(&mut next);
+            assert_eq!(part2_jump_table(&map), part2(&map), "\n{map}");
+        }
+    }
+}
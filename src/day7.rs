@@ -1,4 +1,113 @@
 use aoc_runner_derive::{aoc, aoc_generator};
+use std::{error::Error, fmt};
+
+trait Operator {
+    fn apply(&self, lhs: u64, rhs: u64) -> Option<u64>;
+    fn symbol(&self) -> String;
+}
+
+struct Add;
+struct Mul;
+struct Sub;
+struct Div;
+struct Xor;
+struct Concat(u64);
+
+impl Operator for Add {
+    fn apply(&self, lhs: u64, rhs: u64) -> Option<u64> {
+        lhs.checked_add(rhs)
+    }
+
+    fn symbol(&self) -> String {
+        "+".to_string()
+    }
+}
+
+impl Operator for Mul {
+    fn apply(&self, lhs: u64, rhs: u64) -> Option<u64> {
+        lhs.checked_mul(rhs)
+    }
+
+    fn symbol(&self) -> String {
+        "*".to_string()
+    }
+}
+
+impl Operator for Sub {
+    fn apply(&self, lhs: u64, rhs: u64) -> Option<u64> {
+        lhs.checked_sub(rhs)
+    }
+
+    fn symbol(&self) -> String {
+        "-".to_string()
+    }
+}
+
+impl Operator for Div {
+    fn apply(&self, lhs: u64, rhs: u64) -> Option<u64> {
+        lhs.checked_div(rhs)
+    }
+
+    fn symbol(&self) -> String {
+        "/".to_string()
+    }
+}
+
+impl Operator for Xor {
+    fn apply(&self, lhs: u64, rhs: u64) -> Option<u64> {
+        Some(lhs ^ rhs)
+    }
+
+    fn symbol(&self) -> String {
+        "^".to_string()
+    }
+}
+
+impl Operator for Concat {
+    fn apply(&self, lhs: u64, rhs: u64) -> Option<u64> {
+        let digits = rhs.checked_ilog(self.0).unwrap_or(0) + 1;
+        lhs.checked_mul(self.0.checked_pow(digits)?)?
+            .checked_add(rhs)
+    }
+
+    fn symbol(&self) -> String {
+        match self.0 {
+            10 => "||".to_string(),
+            base => format!("||{base}"),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct UnknownOperator(String);
+
+impl fmt::Display for UnknownOperator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown operator {:?}", self.0)
+    }
+}
+
+impl Error for UnknownOperator {}
+
+// Space separated symbols, e.g. "+ * ||" or "- / ^ ||2"
+fn parse_operators(spec: &str) -> Result<Vec<Box<dyn Operator>>, UnknownOperator> {
+    spec.split(' ')
+        .map(|op| -> Result<Box<dyn Operator>, _> {
+            Ok(match op {
+                "+" => Box::new(Add),
+                "*" => Box::new(Mul),
+                "-" => Box::new(Sub),
+                "/" => Box::new(Div),
+                "^" => Box::new(Xor),
+                "||" => Box::new(Concat(10)),
+                _ => match op.strip_prefix("||").and_then(|b| b.parse().ok()) {
+                    Some(base) if base >= 2 => Box::new(Concat(base)),
+                    _ => return Err(UnknownOperator(op.to_string())),
+                },
+            })
+        })
+        .collect()
+}
 
 struct Equation {
     result: u64,
@@ -13,6 +122,46 @@ impl Equation {
             numbers: l.split(' ').map(|s| s.parse().unwrap()).collect(),
         }
     }
+
+    // First satisfying expression (evaluated left to right) and number of solutions
+    fn solve(&self, ops: &[Box<dyn Operator>]) -> (Option<String>, usize) {
+        let mut first = None;
+        let mut stack = Vec::new();
+        let count = self.solve_from(1, self.numbers[0], ops, &mut stack, &mut first);
+        let expression = first.map(|seq: Vec<usize>| {
+            let mut res = self.numbers[0].to_string();
+            for (&op, n) in seq.iter().zip(&self.numbers[1..]) {
+                res = format!("{res} {} {n}", ops[op].symbol());
+            }
+            res
+        });
+        (expression, count)
+    }
+
+    fn solve_from(
+        &self,
+        idx: usize,
+        current: u64,
+        ops: &[Box<dyn Operator>],
+        stack: &mut Vec<usize>,
+        first: &mut Option<Vec<usize>>,
+    ) -> usize {
+        let Some(&n) = self.numbers.get(idx) else {
+            if current == self.result && first.is_none() {
+                *first = Some(stack.clone());
+            }
+            return (current == self.result) as usize;
+        };
+        let mut count = 0;
+        for (k, op) in ops.iter().enumerate() {
+            if let Some(next) = op.apply(current, n) {
+                stack.push(k);
+                count += self.solve_from(idx + 1, next, ops, stack, first);
+                stack.pop();
+            }
+        }
+        count
+    }
 }
 
 fn is_valid(numbers: &[u64], current: u64, result: u64) -> bool {
@@ -34,6 +183,23 @@ fn is_valid2(numbers: &[u64], current: u64, result: u64) -> bool {
     }
 }
 
+// Undo the last operator, starting from the expected result
+fn is_valid_reverse(numbers: &[u64], target: u64, concat: bool) -> bool {
+    match numbers {
+        [] => false,
+        [n] => *n == target,
+        [t @ .., n] => {
+            (*n == 0 && target == 0)
+                || (*n != 0 && target.is_multiple_of(*n) && is_valid_reverse(t, target / n, concat))
+                || (target >= *n && is_valid_reverse(t, target - n, concat))
+                || (concat && {
+                    let shift = 10u64.pow(n.checked_ilog10().unwrap_or(0) + 1);
+                    target % shift == *n && is_valid_reverse(t, target / shift, concat)
+                })
+        }
+    }
+}
+
 #[aoc_generator(day7)]
 fn input_generator(input: &str) -> Vec<Equation> {
     input.lines().map(Equation::new).collect()
@@ -48,6 +214,15 @@ fn part1(input: &[Equation]) -> u64 {
         .sum()
 }
 
+#[aoc(day7, part1, Reverse)]
+fn part1_reverse(input: &[Equation]) -> u64 {
+    input
+        .iter()
+        .filter(|e| is_valid_reverse(&e.numbers, e.result, false))
+        .map(|e| e.result)
+        .sum()
+}
+
 #[aoc(day7, part2)]
 fn part2(input: &[Equation]) -> u64 {
     input
@@ -56,3 +231,126 @@ fn part2(input: &[Equation]) -> u64 {
         .map(|e| e.result)
         .sum()
 }
+
+#[aoc(day7, part2, Reverse)]
+fn part2_reverse(input: &[Equation]) -> u64 {
+    input
+        .iter()
+        .filter(|e| is_valid_reverse(&e.numbers, e.result, true))
+        .map(|e| e.result)
+        .sum()
+}
+
+fn run_with_operators(input: &[Equation], spec: &str) -> Result<u64, UnknownOperator> {
+    let ops = parse_operators(spec)?;
+    Ok(input
+        .iter()
+        .filter(|e| e.solve(&ops).1 > 0)
+        .map(|e| e.result)
+        .sum())
+}
+
+#[aoc(day7, part1, Operators)]
+fn part1_operators(input: &[Equation]) -> Result<u64, UnknownOperator> {
+    run_with_operators(input, "+ *")
+}
+
+#[aoc(day7, part2, Operators)]
+fn part2_operators(input: &[Equation]) -> Result<u64, UnknownOperator> {
+    run_with_operators(input, "+ * ||")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Lcg;
+
+    const EXAMPLE: &str = "190: 10 19
+3267: 81 40 27
+83: 17 5
+156: 15 6
+7290: 6 8 6 15
+161011: 16 10 13
+192: 17 8 14
+21037: 9 7 18 13
+292: 11 6 16 20";
+
+    #[test]
+    fn example() {
+        let equations = input_generator(EXAMPLE);
+        assert_eq!(part1(&equations), 3749);
+        assert_eq!(part1_reverse(&equations), 3749);
+        assert_eq!(part2(&equations), 11387);
+        assert_eq!(part2_reverse(&equations), 11387);
+        assert_eq!(part1_operators(&equations).unwrap(), 3749);
+        assert_eq!(part2_operators(&equations).unwrap(), 11387);
+    }
+
+    #[test]
+    fn solve() {
+        let cases = [
+            ("190: 10 19", "+ *", Some("10 * 19"), 1),
+            ("3267: 81 40 27", "+ *", Some("81 + 40 * 27"), 2),
+            ("83: 17 5", "+ *", None, 0),
+            ("156: 15 6", "+ * ||", Some("15 || 6"), 1),
+            ("20: 2 0", "||", Some("2 || 0"), 1),
+            ("6: 20 4 10", "- /", Some("20 - 4 - 10"), 1),
+            ("5: 20 4", "- /", Some("20 / 4"), 1),
+            ("0: 3 5", "-", None, 0),
+            ("0: 3 0", "/", None, 0),
+            ("6: 5 3", "^", Some("5 ^ 3"), 1),
+            ("11: 2 3", "+ ||2", Some("2 ||2 3"), 1),
+            ("21: 2 3", "+ ||3", Some("2 ||3 3"), 1),
+        ];
+        for (equation, spec, expression, count) in cases {
+            let ops = parse_operators(spec).ok().unwrap();
+            let (found, n) = Equation::new(equation).solve(&ops);
+            assert_eq!((found.as_deref(), n), (expression, count), "{equation}");
+        }
+    }
+
+    #[test]
+    fn unknown_operator() {
+        for spec in ["+ %", "||1", "||x", "+  *"] {
+            let err = parse_operators(spec).err().map(|e| e.to_string());
+            assert!(err.is_some(), "{spec}");
+        }
+        assert_eq!(
+            parse_operators("+ %").err().unwrap().to_string(),
+            "unknown operator \"%\""
+        );
+        let equations = input_generator("190: 10 19");
+        assert!(run_with_operators(&equations, "+ mod").is_err());
+    }
+
+    #[test]
+    fn reverse_matches_forward() {
+        let mut rng = Lcg::new(7);
+        let mut next = |m: u64| rng.below(m);
+        for _ in 0..3000 {
+            let numbers: Vec<u64> = (0..2 + next(5)).map(|_| 1 + next(20)).collect();
+            // Half the targets are built from random operators so that some are solvable
+            let result = if next(2) == 0 {
+                numbers[1..]
+                    .iter()
+                    .fold(numbers[0], |acc, &n| match next(3) {
+                        0 => acc + n,
+                        1 => acc * n,
+                        _ => acc * 10u64.pow(n.ilog10() + 1) + n,
+                    })
+            } else {
+                1 + next(10000)
+            };
+            assert_eq!(
+                is_valid(&numbers[1..], numbers[0], result),
+                is_valid_reverse(&numbers, result, false),
+                "{result}: {numbers:?}"
+            );
+            assert_eq!(
+                is_valid2(&numbers[1..], numbers[0], result),
+                is_valid_reverse(&numbers, result, true),
+                "{result}: {numbers:?}"
+            );
+        }
+    }
+}
@@ -1,6 +1,17 @@
 use aoc_runner_derive::aoc;
 use itertools::Itertools;
 use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+type Point = (i64, i64);
+type Antinode = (Point, char, (Point, Point));
+
+fn gcd(a: i64, b: i64) -> i64 {
+    match b {
+        0 => a.abs(),
+        _ => gcd(b, a % b),
+    }
+}
 
 struct Grid {
     height: usize,
@@ -65,6 +76,129 @@ impl Grid {
         }
         antinodes
     }
+
+    fn contains(&self, (x, y): Point) -> bool {
+        (0..self.height as i64).contains(&x) && (0..self.width as i64).contains(&y)
+    }
+
+    fn signed_pairs(&self) -> impl Iterator<Item = (char, Point, Point)> + '_ {
+        self.antennas.iter().flat_map(|(&freq, antennas)| {
+            antennas
+                .iter()
+                .map(|&(x, y)| (x as i64, y as i64))
+                .permutations(2)
+                .map(move |pair| (freq, pair[0], pair[1]))
+        })
+    }
+
+    // Points on the line through each pair where the distance to the first antenna
+    // is num/den times the distance to the second one. Both orders of each pair are
+    // visited, so num < den finds the same points as den/num. A 1:1 ratio has no
+    // point outside the pair, only the midpoint
+    fn ratio_antinodes(&self, (num, den): (i64, i64)) -> Vec<Antinode> {
+        assert!(num > 0 && den > 0, "ratio {num}/{den} must be positive");
+        let mut antinodes = Vec::new();
+        for (freq, a, b) in self.signed_pairs() {
+            let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+            let candidates = [
+                (dx * den, dy * den, num - den, b),
+                (dx * num, dy * num, num + den, a),
+            ];
+            for (nx, ny, d, origin) in candidates {
+                if d != 0 && nx % d == 0 && ny % d == 0 {
+                    let node = (origin.0 + nx / d, origin.1 + ny / d);
+                    if self.contains(node) {
+                        antinodes.push((node, freq, (a, b)));
+                    }
+                }
+            }
+        }
+        antinodes
+    }
+
+    // Every grid point on the line through each pair
+    fn line_antinodes(&self) -> Vec<Antinode> {
+        let mut antinodes = Vec::new();
+        for (freq, a, b) in self.signed_pairs().filter(|(_, a, b)| a < b) {
+            let g = gcd(b.0 - a.0, b.1 - a.1);
+            let (sx, sy) = ((b.0 - a.0) / g, (b.1 - a.1) / g);
+            for (mut node, (sx, sy)) in [(a, (sx, sy)), ((a.0 - sx, a.1 - sy), (-sx, -sy))] {
+                while self.contains(node) {
+                    antinodes.push((node, freq, (a, b)));
+                    node = (node.0 + sx, node.1 + sy);
+                }
+            }
+        }
+        antinodes
+    }
+
+    fn render(&self, antinodes: &[Antinode]) -> String {
+        let nodes: HashSet<_> = antinodes.iter().map(|&(node, _, _)| node).collect();
+        let mut rows = vec![vec!['.'; self.width]; self.height];
+        for &(x, y) in &nodes {
+            rows[x as usize][y as usize] = '#';
+        }
+        for (&freq, antennas) in &self.antennas {
+            for &(x, y) in antennas {
+                rows[x][y] = freq;
+            }
+        }
+        rows.into_iter().map(String::from_iter).join("\n")
+    }
+
+    fn report(&self, antinodes: &[Antinode]) -> String {
+        let mut by_freq: HashMap<char, HashSet<Point>> = HashMap::new();
+        for &(node, freq, _) in antinodes {
+            by_freq.entry(freq).or_default().insert(node);
+        }
+        let on_antennas: HashSet<_> = self
+            .antennas
+            .values()
+            .flatten()
+            .map(|&(x, y)| (x as i64, y as i64))
+            .collect();
+        let mut lines = Vec::new();
+        for freq in self.antennas.keys().sorted() {
+            let nodes = by_freq.remove(freq).unwrap_or_default();
+            let overlaps = nodes
+                .iter()
+                .filter(|node| {
+                    antinodes
+                        .iter()
+                        .any(|(other, f, _)| f != freq && other == *node)
+                })
+                .count();
+            lines.push(format!(
+                "{freq}: {} antennas, {} antinodes, {} shared with other frequencies, {} on antennas",
+                self.antennas[freq].len(),
+                nodes.len(),
+                overlaps,
+                nodes.intersection(&on_antennas).count()
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+// The per-frequency report followed by the map with the antinodes drawn on it
+struct Survey<'a> {
+    grid: &'a Grid,
+    antinodes: Vec<Antinode>,
+}
+
+impl fmt::Display for Survey<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.grid.report(&self.antinodes))?;
+        write!(f, "{}", self.grid.render(&self.antinodes))
+    }
+}
+
+fn count_unique(antinodes: Vec<Antinode>) -> usize {
+    antinodes
+        .into_iter()
+        .map(|(node, _, _)| node)
+        .unique()
+        .count()
 }
 
 #[aoc(day8, part1)]
@@ -76,3 +210,114 @@ fn part1(input: &str) -> usize {
 fn part2(input: &str) -> usize {
     Grid::new(input).get_all_antinodes().len()
 }
+
+#[aoc(day8, part1, Engine)]
+fn part1_engine(input: &str) -> usize {
+    count_unique(Grid::new(input).ratio_antinodes((2, 1)))
+}
+
+#[aoc(day8, part2, Engine)]
+fn part2_engine(input: &str) -> usize {
+    count_unique(Grid::new(input).line_antinodes())
+}
+
+fn survey_count(survey: Survey) -> usize {
+    count_unique(survey.antinodes)
+}
+
+#[aoc(day8, part1, Report)]
+fn part1_report(input: &str) -> usize {
+    let grid = Grid::new(input);
+    survey_count(Survey {
+        antinodes: grid.ratio_antinodes((2, 1)),
+        grid: &grid,
+    })
+}
+
+#[aoc(day8, part2, Report)]
+fn part2_report(input: &str) -> usize {
+    let grid = Grid::new(input);
+    survey_count(Survey {
+        antinodes: grid.line_antinodes(),
+        grid: &grid,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "............
+........0...
+.....0......
+.......0....
+....0.......
+......A.....
+............
+............
+........A...
+.........A..
+............
+............";
+
+    #[test]
+    fn example() {
+        for part1 in [part1, part1_engine, part1_report] {
+            assert_eq!(part1(EXAMPLE), 14);
+        }
+        for part2 in [part2, part2_engine, part2_report] {
+            assert_eq!(part2(EXAMPLE), 34);
+        }
+    }
+
+    #[test]
+    fn survey() {
+        let grid = Grid::new(EXAMPLE);
+        let first = grid.ratio_antinodes((2, 1));
+        assert_eq!(
+            grid.report(&first),
+            "0: 4 antennas, 10 antinodes, 1 shared with other frequencies, 1 on antennas
+A: 3 antennas, 5 antinodes, 1 shared with other frequencies, 0 on antennas"
+        );
+        assert_eq!(
+            grid.render(&first),
+            "......#....#
+...#....0...
+....#0....#.
+..#....0....
+....0....#..
+.#....A.....
+...#........
+#......#....
+........A...
+.........A..
+..........#.
+..........#."
+        );
+        let all = grid.line_antinodes();
+        assert_eq!(
+            grid.report(&all),
+            "0: 4 antennas, 21 antinodes, 3 shared with other frequencies, 5 on antennas
+A: 3 antennas, 16 antinodes, 3 shared with other frequencies, 4 on antennas"
+        );
+        let survey = Survey {
+            grid: &grid,
+            antinodes: all,
+        };
+        assert_eq!(
+            survey.to_string().lines().skip(2).join("\n"),
+            "##....#....#
+.#.#....0...
+..#.#0....#.
+..##...0....
+....0....#..
+.#...#A....#
+...#..#.....
+#....#.#....
+..#.....A...
+....#....A..
+.#........#.
+...#......##"
+        );
+    }
+}
@@ -1,4 +1,123 @@
 use aoc_runner_derive::aoc;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt;
+
+#[derive(Clone, Copy)]
+enum Strategy {
+    Blocks,
+    Files,
+    BestFit,
+}
+
+struct Disk {
+    blocks: Vec<Option<usize>>,
+    files: Vec<(usize, usize)>,
+}
+
+impl Disk {
+    fn new(input: &str) -> Self {
+        let mut blocks = Vec::new();
+        let mut files = Vec::new();
+        for (i, c) in input.trim().chars().enumerate() {
+            let len = c.to_digit(10).unwrap() as usize;
+            if i % 2 == 0 {
+                files.push((blocks.len(), len));
+                blocks.extend(std::iter::repeat_n(Some(i / 2), len));
+            } else {
+                blocks.extend(std::iter::repeat_n(None, len));
+            }
+        }
+        Disk { blocks, files }
+    }
+
+    // File ids are shown modulo 10, as in the puzzle examples
+    fn render(&self) -> String {
+        self.blocks
+            .iter()
+            .map(|b| b.map_or('.', |id| char::from_digit((id % 10) as u32, 10).unwrap()))
+            .collect()
+    }
+
+    fn checksum(&self) -> usize {
+        self.blocks
+            .iter()
+            .enumerate()
+            .filter_map(|(i, b)| b.map(|id| i * id))
+            .sum()
+    }
+
+    fn compact(&mut self, strategy: Strategy) {
+        match strategy {
+            Strategy::Blocks => self.compact_blocks(),
+            Strategy::Files | Strategy::BestFit => {
+                for id in (0..self.files.len()).rev() {
+                    if let Some(start) = self.find_gap(id, strategy) {
+                        self.move_file(id, start);
+                    }
+                }
+            }
+        }
+    }
+
+    fn compact_blocks(&mut self) {
+        let (mut i, mut j) = (0, self.blocks.len());
+        loop {
+            while i < j && self.blocks[i].is_some() {
+                i += 1;
+            }
+            while i < j && self.blocks[j - 1].is_none() {
+                j -= 1;
+            }
+            if i >= j {
+                break;
+            }
+            self.blocks.swap(i, j - 1);
+        }
+    }
+
+    // Start of the free span left of the file chosen to hold it
+    fn find_gap(&self, id: usize, strategy: Strategy) -> Option<usize> {
+        let (file_start, file_len) = self.files[id];
+        let mut gaps = Vec::new();
+        let mut i = 0;
+        while i < file_start {
+            let len = self.blocks[i..file_start]
+                .iter()
+                .take_while(|b| b.is_none())
+                .count();
+            if len >= file_len && len > 0 {
+                gaps.push((i, len));
+            }
+            i += len.max(1);
+        }
+        match strategy {
+            Strategy::BestFit => gaps.into_iter().min_by_key(|&(start, len)| (len, start)),
+            _ => gaps.into_iter().next(),
+        }
+        .map(|(start, _)| start)
+    }
+
+    fn move_file(&mut self, id: usize, start: usize) {
+        let (old_start, len) = self.files[id];
+        for k in 0..len {
+            self.blocks.swap(old_start + k, start + k);
+        }
+        self.files[id] = (start, len);
+    }
+}
+
+impl fmt::Display for Disk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.render())
+    }
+}
+
+fn run(input: &str, strategy: Strategy) -> usize {
+    let mut disk = Disk::new(input);
+    disk.compact(strategy);
+    disk.checksum()
+}
 
 #[aoc(day9, part1)]
 fn part1(input: &str) -> usize {
@@ -37,6 +156,11 @@ fn part1(input: &str) -> usize {
     panic!("Unreachable")
 }
 
+#[aoc(day9, part1, Disk)]
+fn part1_disk(input: &str) -> usize {
+    run(input, Strategy::Blocks)
+}
+
 #[aoc(day9, part2)]
 fn part2(input: &str) -> usize {
     let mut files = Vec::new();
@@ -71,3 +195,79 @@ fn part2(input: &str) -> usize {
     }
     res
 }
+
+// Free spans are kept in one min-heap of starts per span length, so each file
+// only checks the leftmost span of every length it fits in
+#[aoc(day9, part2, Heaps)]
+fn part2_heaps(input: &str) -> usize {
+    let mut files = Vec::new();
+    let mut spaces: [BinaryHeap<Reverse<usize>>; 10] = Default::default();
+    let mut index = 0;
+    for (i, len) in input
+        .trim()
+        .chars()
+        .map(|c| c.to_digit(10).unwrap() as usize)
+        .enumerate()
+    {
+        if i % 2 == 0 {
+            files.push((index, len, i / 2));
+        } else if len > 0 {
+            spaces[len].push(Reverse(index));
+        }
+        index += len;
+    }
+    let mut res = 0;
+    for &(mut file_index, file_len, file_id) in files.iter().rev() {
+        let best = (file_len.max(1)..10)
+            .filter_map(|len| spaces[len].peek().map(|&Reverse(start)| (start, len)))
+            .filter(|&(start, _)| start < file_index)
+            .min();
+        if let Some((start, len)) = best {
+            spaces[len].pop();
+            if len > file_len {
+                spaces[len - file_len].push(Reverse(start + file_len));
+            }
+            file_index = start;
+        }
+        res += file_id * (file_index * file_len + file_len * file_len.saturating_sub(1) / 2);
+    }
+    res
+}
+
+#[aoc(day9, part2, Disk)]
+fn part2_disk(input: &str) -> usize {
+    run(input, Strategy::Files)
+}
+
+#[aoc(day9, part2, BestFit)]
+fn part2_best_fit(input: &str) -> usize {
+    run(input, Strategy::BestFit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng::Lcg;
+
+    #[test]
+    fn example() {
+        assert_eq!(part2("2333133121414131402"), 2858);
+        assert_eq!(part2_heaps("2333133121414131402"), 2858);
+    }
+
+    #[test]
+    fn heaps_match_files() {
+        let mut rng = Lcg::new(9);
+        let mut next = |m: u64| rng.below(m);
+        for _ in 0..2000 {
+            let len = 1 + 2 * next(20);
+            let map: String = (0..len)
+                .map(|i| match i % 2 {
+                    0 => char::from_digit(1 + next(9) as u32, 10).unwrap(),
+                    _ => char::from_digit(next(10) as u32, 10).unwrap(),
+                })
+                .collect();
+            assert_eq!(part2_heaps(&map), part2(&map), "{map}");
+        }
+    }
+}
@@ -25,5 +25,7 @@ mod day6;
 mod day7;
 mod day8;
 mod day9;
+#[cfg(test)]
+mod rng;
 
 aoc_lib! { year = 2024 }